@@ -1,4 +1,4 @@
-use std::{collections::HashMap, env};
+use std::collections::HashMap;
 
 use anyhow::Context;
 use reqwest::Url;
@@ -6,43 +6,69 @@ use serde_json::{json, Value};
 
 use crate::repository::Note;
 
-pub async fn search(query: &str) -> anyhow::Result<HashMap<usize, f64>> {
-    let ai_server = env::var("AI_ENGINE")
-        .context("connecting to ai embedding engine")
-        .unwrap();
-    let client = reqwest::Client::new();
-    let body = HashMap::from([("query", query)]);
-    let ai_server = Url::parse(&ai_server)?.join("/search")?;
-    client
-        .get(ai_server)
-        .json(&body)
-        .send()
-        .await?
-        .json::<HashMap<usize, f64>>()
-        .await
-        .context("search request error!")
+/// client of the ai embedding engine at the `ai_engine` url of the config
+#[derive(Debug, Clone, Default)]
+pub struct AiClient {
+    url: Option<String>,
+    client: reqwest::Client,
 }
 
-pub async fn add(notes: &Vec<Note>) -> anyhow::Result<()> {
-    let ai_server = env::var("AI_ENGINE")
-        .context("connecting to ai embedding engine")
-        .unwrap();
-    let client = reqwest::Client::new();
-    let ai_server = Url::parse(&ai_server)?.join("/add")?;
-    for note in notes {
-        let body = HashMap::from([
-            ("id", json!(note.id)),
-            ("sentence", json!(String::from(note))),
-        ]);
-        let payload: Value = serde_json::to_value(body)?;
-        _ = client
-            .post(ai_server.clone())
-            .json(&payload)
+impl AiClient {
+    pub fn new(url: Option<String>) -> AiClient {
+        AiClient {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.url.is_some()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    fn endpoint(&self, path: &str) -> anyhow::Result<Url> {
+        let url = self
+            .url
+            .as_deref()
+            .context("no ai embedding engine, set ai_engine in the config or AI_ENGINE")?;
+        Ok(Url::parse(url)
+            .with_context(|| format!("ai engine url {url}"))?
+            .join(path)?)
+    }
+
+    pub async fn search(&self, query: &str) -> anyhow::Result<HashMap<usize, f64>> {
+        let body = HashMap::from([("query", query)]);
+        self.client
+            .get(self.endpoint("/search")?)
+            .json(&body)
             .send()
             .await?
-            .text()
+            .json::<HashMap<usize, f64>>()
             .await
-            .context("add request error!");
+            .context("search request error!")
+    }
+
+    pub async fn add(&self, notes: &[Note]) -> anyhow::Result<()> {
+        let ai_server = self.endpoint("/add")?;
+        for note in notes {
+            let body = HashMap::from([
+                ("id", json!(note.id)),
+                ("sentence", json!(String::from(note))),
+            ]);
+            let payload: Value = serde_json::to_value(body)?;
+            _ = self
+                .client
+                .post(ai_server.clone())
+                .json(&payload)
+                .send()
+                .await?
+                .text()
+                .await
+                .context("add request error!");
+        }
+        Ok(())
     }
-    Ok(())
 }
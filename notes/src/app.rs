@@ -1,10 +1,17 @@
-use std::mem;
+use std::collections::{HashMap, HashSet};
 
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use ratatui::widgets::ListState;
 
-use crate::editor_handler::edit_with_vim;
+use crate::{
+    config::Config,
+    editor_handler::edit_with,
+    keymap::{Action, KeyScope, Keymap},
+    repository::{Note, NoteLink, Notebook, Revision},
+    service::{NoteService, SortOrder},
+    theme::Theme,
+};
 
 pub struct App {
     pub editor_mode: EditorMode,
@@ -13,81 +20,444 @@ pub struct App {
     pub mode: AppMode,
     pub tabs: StatefulList,
     pub note_list: NoteList,
+    /// revisions of a note, shown instead of the notes while open
+    pub history: Option<History>,
+    pub trash: TrashList,
+    /// destructive action waiting for a yes
+    pub confirm: Option<Confirm>,
+    /// what `u` reverts
+    undo: Option<Undo>,
+    pub service: NoteService,
     pub search_key_word_related: Vec<usize>,
     matcher: Matcher,
     pub search_query: String,
     is_searched_changed: bool,
+    /// show the markdown of notes as written instead of rendered
+    pub raw_content: bool,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    /// settings of the open profile, switching vaults selects another profile
+    pub config: Config,
+    /// profiles to switch to, shown over the view while open
+    pub vaults: Option<Vaults>,
+    /// passphrase of an encrypted vault being switched to
+    pub unlock: Option<Unlock>,
+    /// the bindings of the current view are listed over it
+    pub show_help: bool,
+    /// whether keys are typed into the search box or run actions
+    pub input_mode: InputMode,
+    /// position in `search_query`, in characters
+    pub search_cursor: usize,
+    /// why the last action failed, shown in the footer until the next key
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    #[default]
+    Normal,
+    Insert,
 }
 
 impl App {
-    pub fn new() -> App {
-        App {
+    /// the keymap and the theme of the config are loaded here
+    pub fn new(service: NoteService, config: &Config) -> anyhow::Result<App> {
+        let mut note_list = NoteList::default();
+        note_list.reload(&service)?;
+        let mut trash = TrashList::default();
+        trash.reload(&service)?;
+        Ok(App {
             editor_mode: EditorMode::None,
             logo_position: 0,
             should_quit: false,
             mode: AppMode::Home,
             tabs: StatefulList::default(),
-            note_list: NoteList::default(),
+            note_list,
+            history: None,
+            trash,
+            confirm: None,
+            undo: None,
+            service,
             search_key_word_related: Vec::new(),
             is_searched_changed: false,
             matcher: Matcher::default(),
             search_query: String::new(),
-        }
+            raw_content: false,
+            keymap: config.keymap()?,
+            theme: config.theme()?,
+            config: config.clone(),
+            vaults: None,
+            unlock: None,
+            show_help: false,
+            input_mode: InputMode::Normal,
+            search_cursor: 0,
+            status: None,
+        })
     }
 
-    pub fn handle_press(&mut self, event: KeyEvent) {
-        match self.mode {
-            AppMode::Home => self.app_handler(event),
-            AppMode::NoteView => {
-                self.app_handler(event);
-                self.note_view_handler(event);
+    pub fn handle_press(&mut self, event: KeyEvent) -> anyhow::Result<()> {
+        if self.show_help {
+            self.show_help = false;
+            return Ok(());
+        }
+        if let Some(confirm) = self.confirm.take() {
+            return self.confirm_handler(confirm, event);
+        }
+        if let Some(unlock) = self.unlock.as_mut() {
+            match unlock.handle_key(event) {
+                UnlockKey::Typed => (),
+                UnlockKey::Cancel => self.unlock = None,
+                UnlockKey::Submit(passphrase) => self.unlock_vault(&passphrase)?,
             }
-            AppMode::Search => {
-                self.search_handler(event);
-                self.app_handler(event)
+            return Ok(());
+        }
+        let scope = self.key_scope();
+        if scope == KeyScope::Vaults {
+            return match self.keymap.action(scope, event) {
+                Some(action) => self.vaults_handler(action),
+                None => Ok(()),
+            };
+        }
+        if self.input_mode == InputMode::Insert {
+            match self.keymap.action(scope, event) {
+                Some(Action::NormalMode) => self.input_mode = InputMode::Normal,
+                // ctrl and alt keys aren't typed, but quitting still works
+                _ if !is_typed(event) => {
+                    if self.keymap.action(KeyScope::Global, event) == Some(Action::Quit) {
+                        self.should_quit = true;
+                    }
+                }
+                _ => self.search_handler(event),
             }
-            _ => self.app_handler(event),
+            return Ok(());
         }
+        let Some(action) = self.keymap.action(scope, event) else {
+            return Ok(());
+        };
+        match scope {
+            KeyScope::Notes => self.note_view_handler(action)?,
+            KeyScope::History => self.history_handler(action)?,
+            KeyScope::Trash => self.trash_handler(action)?,
+            KeyScope::Search if action == Action::InsertMode => self.input_mode = InputMode::Insert,
+            _ => (),
+        }
+        self.app_handler(action);
+        Ok(())
     }
 
+    /// where the bindings of the next key press come from
+    pub fn key_scope(&self) -> KeyScope {
+        match self.mode {
+            _ if self.confirm.is_some() => KeyScope::Confirm,
+            _ if self.vaults.is_some() => KeyScope::Vaults,
+            _ if self.input_mode == InputMode::Insert => KeyScope::Insert,
+            AppMode::NoteView if self.history.is_some() => KeyScope::History,
+            AppMode::NoteView => KeyScope::Notes,
+            AppMode::Trash => KeyScope::Trash,
+            AppMode::Search => KeyScope::Search,
+            AppMode::Home | AppMode::Chat => KeyScope::Global,
+        }
+    }
+
+    /// edits the query at the cursor, enter stops typing
     pub fn search_handler(&mut self, event: KeyEvent) {
+        let len = self.search_query.chars().count();
+        let at = |query: &str, cursor: usize| {
+            query
+                .char_indices()
+                .nth(cursor)
+                .map_or(query.len(), |(index, _)| index)
+        };
         match event.code {
-            KeyCode::Char(key) => self.search_query.push(key),
-            KeyCode::Backspace => {
-                self.search_query.pop();
+            KeyCode::Char(key) if is_typed(event) => {
+                let index = at(&self.search_query, self.search_cursor);
+                self.search_query.insert(index, key);
+                self.search_cursor += 1;
+            }
+            KeyCode::Backspace if self.search_cursor > 0 => {
+                self.search_cursor -= 1;
+                let index = at(&self.search_query, self.search_cursor);
+                self.search_query.remove(index);
                 self.is_searched_changed = false
             }
-            _ => (),
+            KeyCode::Delete if self.search_cursor < len => {
+                let index = at(&self.search_query, self.search_cursor);
+                self.search_query.remove(index);
+                self.is_searched_changed = false
+            }
+            KeyCode::Left => self.search_cursor = self.search_cursor.saturating_sub(1),
+            KeyCode::Right => self.search_cursor = (self.search_cursor + 1).min(len),
+            KeyCode::Home => self.search_cursor = 0,
+            KeyCode::End => self.search_cursor = len,
+            KeyCode::Enter => self.input_mode = InputMode::Normal,
+            _ => return,
         };
         self.search_keyword();
     }
 
-    fn app_handler(&mut self, event: KeyEvent) {
-        match event.code {
-            KeyCode::Right => self.tabs.next(),
-            KeyCode::Left => self.tabs.previous(),
-            KeyCode::Char('q') => self.should_quit = true,
+    fn app_handler(&mut self, action: Action) {
+        match action {
+            Action::NextTab => self.tabs.next(),
+            Action::PreviousTab => self.tabs.previous(),
+            Action::Quit => self.should_quit = true,
+            Action::Help => self.show_help = true,
+            Action::Vaults => self.vaults = Some(Vaults::new(&self.config)),
             _ => (),
         };
         self.set_app_mode()
     }
 
-    fn note_view_handler(&mut self, event: KeyEvent) {
-        match event.code {
-            KeyCode::Up => self.note_list.previous(),
-            KeyCode::Down => self.note_list.next(),
-            KeyCode::Char('a') => {
-                self.editor_mode = EditorMode::Add;
+    fn note_view_handler(&mut self, action: Action) -> anyhow::Result<()> {
+        let content_focused = self.note_list.focus == Focus::Content;
+        match action {
+            Action::ToggleFocus => self.note_list.toggle_focus(),
+            Action::Up if content_focused => self.note_list.scroll_by(-1),
+            Action::Down if content_focused => self.note_list.scroll_by(1),
+            Action::PageUp => self.note_list.scroll_page(-1),
+            Action::PageDown => self.note_list.scroll_page(1),
+            Action::Top => self.note_list.scroll_to(0),
+            Action::Bottom => self.note_list.scroll_to(u16::MAX),
+            Action::Up => self.note_list.previous(),
+            Action::Down => self.note_list.next(),
+            Action::Fold => self.note_list.toggle_selected_notebook(),
+            Action::AddNote => self.editor_mode = EditorMode::Add,
+            Action::EditNote => self.editor_mode = EditorMode::Edit,
+            Action::NewNotebook => self.editor_mode = EditorMode::Notebook,
+            Action::Delete => {
+                if let Some(note) = self.note_list.get_selected() {
+                    self.confirm = note.id.map(|id| Confirm::Delete {
+                        id,
+                        title: note.title.clone(),
+                    });
+                }
+            }
+            Action::Undo => self.undo()?,
+            Action::Cut => self.note_list.cut_note(),
+            Action::Paste => self.note_list.paste_note(&mut self.service)?,
+            Action::NextLink => self.note_list.next_link(),
+            Action::FollowLink => self.note_list.follow_link(),
+            Action::Back => self.note_list.go_back(),
+            Action::History => self.open_history()?,
+            Action::Sort => self.note_list.set_sort(self.note_list.sort.next()),
+            Action::Pin => self.note_list.toggle_pin(&self.service)?,
+            Action::ToggleRaw => self.raw_content = !self.raw_content,
+            _ => (),
+        };
+        self.note_list.load_links(&self.service)
+    }
+
+    fn trash_handler(&mut self, action: Action) -> anyhow::Result<()> {
+        match action {
+            Action::Up => self.trash.previous(),
+            Action::Down => self.trash.next(),
+            Action::Restore => {
+                if let Some(id) = self.trash.get_selected().and_then(|note| note.id) {
+                    self.service.restore_note(id)?;
+                    self.reload()?;
+                }
+            }
+            Action::Delete => {
+                if let Some(note) = self.trash.get_selected() {
+                    self.confirm = note.id.map(|id| Confirm::Purge {
+                        id,
+                        title: note.title.clone(),
+                    });
+                }
+            }
+            Action::Undo => self.undo()?,
+            _ => (),
+        };
+        Ok(())
+    }
+
+    /// runs the action on a yes, any other key drops it
+    fn confirm_handler(&mut self, confirm: Confirm, event: KeyEvent) -> anyhow::Result<()> {
+        if self.keymap.action(KeyScope::Confirm, event) != Some(Action::Yes) {
+            return Ok(());
+        }
+        match confirm {
+            Confirm::Delete { id, .. } => {
+                self.note_list.delete_note(&mut self.service, id)?;
+                self.undo = Some(Undo::Delete(id));
+            }
+            Confirm::Purge { id, .. } => {
+                self.service.purge_note(id)?;
+                if self.undo == Some(Undo::Delete(id)) {
+                    self.undo = None;
+                }
+            }
+        }
+        self.reload()
+    }
+
+    /// reverts the last delete or edit
+    fn undo(&mut self) -> anyhow::Result<()> {
+        match self.undo.take() {
+            Some(Undo::Delete(id)) => self.service.restore_note(id)?,
+            Some(Undo::Edit { id, revision }) => self.service.restore_revision(id, revision)?,
+            None => return Ok(()),
+        }
+        self.reload()
+    }
+
+    /// moves through the profiles and switches to the selected one
+    fn vaults_handler(&mut self, action: Action) -> anyhow::Result<()> {
+        let Some(vaults) = self.vaults.as_mut() else {
+            return Ok(());
+        };
+        match action {
+            Action::Up => vaults.previous(),
+            Action::Down => vaults.next(),
+            Action::Select => {
+                let name = vaults.selected().map(str::to_string);
+                self.vaults = None;
+                if let Some(name) = name {
+                    self.switch_vault(&name)?;
+                }
+            }
+            Action::Close => self.vaults = None,
+            Action::Quit => self.should_quit = true,
+            Action::Help => self.show_help = true,
+            _ => (),
+        };
+        Ok(())
+    }
+
+    /// opens the database of another profile, what belonged to the old one is dropped.
+    /// an encrypted one asks for its passphrase first
+    fn switch_vault(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.config.profile.as_deref() == Some(name) {
+            return Ok(());
+        }
+        let mut config = self.config.clone();
+        config.select_profile(Some(name))?;
+        if NoteService::needs_passphrase(&config)? {
+            self.unlock = Some(Unlock::new(Some(name.to_string())));
+            return Ok(());
+        }
+        let service = NoteService::open(&config)?;
+        self.open_vault(config, service)
+    }
+
+    /// opens the profile waiting for its passphrase, a wrong one is asked again
+    fn unlock_vault(&mut self, passphrase: &str) -> anyhow::Result<()> {
+        let Some(unlock) = self.unlock.as_mut() else {
+            return Ok(());
+        };
+        let mut config = self.config.clone();
+        config.select_profile(unlock.profile.as_deref())?;
+        match NoteService::open_with(&config, Some(passphrase)) {
+            Ok(service) => {
+                self.unlock = None;
+                self.open_vault(config, service)
+            }
+            Err(err) => {
+                unlock.error = Some(err.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn open_vault(&mut self, config: Config, mut service: NoteService) -> anyhow::Result<()> {
+        service.purge_expired()?;
+        self.service = service;
+        self.config = config;
+        let sort = self.note_list.sort;
+        self.note_list = NoteList::default();
+        self.note_list.set_sort(sort);
+        self.trash = TrashList::default();
+        self.history = None;
+        self.undo = None;
+        self.search_query.clear();
+        self.search_cursor = 0;
+        self.search_key_word_related.clear();
+        self.reload()
+    }
+
+    /// loads notes and trash again, after the service changed them
+    fn reload(&mut self) -> anyhow::Result<()> {
+        self.note_list.reload(&self.service)?;
+        self.trash.reload(&self.service)
+    }
+
+    fn history_handler(&mut self, action: Action) -> anyhow::Result<()> {
+        let Some(history) = self.history.as_mut() else {
+            return Ok(());
+        };
+        match action {
+            Action::Up => history.previous(),
+            Action::Down => history.next(),
+            Action::Restore => {
+                if let Some(revision) = history.selected() {
+                    let (id, revision) = (revision.note_id, revision.revision);
+                    self.service.restore_revision(id, revision)?;
+                    self.note_list.reload(&self.service)?;
+                    self.open_history()?;
+                }
             }
-            KeyCode::Char('e') => self.editor_mode = EditorMode::Edit,
-            KeyCode::Char('d') => self.note_list.delete_note(),
+            Action::Close => self.history = None,
             _ => (),
         };
+        Ok(())
+    }
+
+    /// shows the revisions of the selected note, the newest one selected
+    fn open_history(&mut self) -> anyhow::Result<()> {
+        let Some(id) = self.note_list.get_selected().and_then(|note| note.id) else {
+            return Ok(());
+        };
+        let revisions = self.service.get_history(id)?;
+        let mut state = ListState::default();
+        state.select(revisions.len().checked_sub(1));
+        self.history = Some(History { revisions, state });
+        Ok(())
+    }
+
+    /// runs the editor requested by the last key press, call it while the terminal is released
+    pub fn run_editor(&mut self) -> anyhow::Result<()> {
+        let result = match self.editor_mode {
+            EditorMode::Add => self
+                .note_list
+                .get_user_note(&mut self.service, &self.config.editor),
+            EditorMode::Edit => self.edit_note(),
+            EditorMode::Notebook => self
+                .note_list
+                .get_user_notebook(&self.service, &self.config.editor),
+            EditorMode::None => Ok(()),
+        };
+        self.editor_mode = EditorMode::None;
+        result
+    }
+
+    /// edits the selected note, remembering the revision it had to undo the edit
+    fn edit_note(&mut self) -> anyhow::Result<()> {
+        let Some(id) = self.note_list.get_selected().and_then(|note| note.id) else {
+            return Ok(());
+        };
+        let latest_revision = |service: &NoteService| -> anyhow::Result<Option<usize>> {
+            Ok(service.get_history(id)?.last().map(|rev| rev.revision))
+        };
+        let before = latest_revision(&self.service)?;
+        self.note_list
+            .edit_note(&mut self.service, &self.config.editor)?;
+        if let Some(revision) = before {
+            if latest_revision(&self.service)? != before {
+                self.undo = Some(Undo::Edit { id, revision });
+            }
+        }
+        Ok(())
     }
 
+    /// the search box takes the keys as soon as its tab opens
     fn set_app_mode(&mut self) {
         let state = self.tabs.state.selected().unwrap_or(0);
+        let was_search = matches!(self.mode, AppMode::Search);
         self.mode = state.into();
+        match self.mode {
+            AppMode::Search if !was_search => self.input_mode = InputMode::Insert,
+            AppMode::Search => (),
+            _ => self.input_mode = InputMode::Normal,
+        }
     }
 
     pub fn clibrate_logo_position(&mut self, max_length: u16) {
@@ -107,7 +477,7 @@ impl App {
         let notes = if self.is_searched_changed {
             self.get_search_result()
         } else {
-            self.note_list.notes.iter().map(|note| note).collect()
+            self.note_list.notes.iter().collect()
         };
         let mut matched_indices: Vec<_> = self
             .matcher
@@ -122,6 +492,16 @@ impl App {
         if self.search_query.is_empty() {
             self.search_key_word_related = Vec::new();
         }
+        let all: Vec<&Note> = self.note_list.notes.iter().collect();
+        let relevance = match self.search_query.is_empty() {
+            true => HashMap::new(),
+            false => all
+                .iter()
+                .zip(self.matcher.match_all(&self.search_query, &all))
+                .filter_map(|(note, score)| Some((note.id?, score)))
+                .collect(),
+        };
+        self.note_list.set_relevance(relevance);
         self.get_search_result()
     }
 }
@@ -131,6 +511,7 @@ pub enum AppMode {
     NoteView,
     Search,
     Chat,
+    Trash,
 }
 impl From<usize> for AppMode {
     fn from(value: usize) -> Self {
@@ -139,10 +520,28 @@ impl From<usize> for AppMode {
             1 => AppMode::NoteView,
             2 => AppMode::Search,
             3 => AppMode::Chat,
+            4 => AppMode::Trash,
             _ => AppMode::Home,
         }
     }
 }
+
+/// destructive action the user has to confirm
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confirm {
+    /// move the note to the trash
+    Delete { id: usize, title: String },
+    /// delete the note in the trash for good
+    Purge { id: usize, title: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Undo {
+    /// the note went to the trash
+    Delete(usize),
+    /// the note was edited, `revision` is what it was before
+    Edit { id: usize, revision: usize },
+}
 pub struct StatefulList {
     pub state: ListState,
     pub items: Vec<&'static str>,
@@ -183,31 +582,272 @@ impl Default for StatefulList {
     fn default() -> Self {
         StatefulList {
             state: ListState::default(),
-            items: vec!["Home", "Notes", "Search", "Chat"],
+            items: vec!["Home", "Notes", "Search", "Chat", "Trash"],
             last_selected: None,
         }
     }
 }
 
+/// a line of the notes tree, notes point into `NoteList::notes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeRow {
+    Notebook { id: usize, depth: usize },
+    Note { index: usize, depth: usize },
+}
+
 #[derive(Debug, Default)]
 pub struct NoteList {
     pub state: ListState,
     pub last_selected: Option<usize>,
     pub notes: Vec<Note>,
+    pub notebooks: Vec<Notebook>,
+    /// visible lines of the tree, rebuilt whenever notes or notebooks change
+    pub rows: Vec<TreeRow>,
+    /// notebooks whose content is hidden
+    pub collapsed: HashSet<usize>,
+    /// note waiting to be pasted in another notebook
+    pub cut: Option<usize>,
+    /// links written in the selected note
+    pub links: Vec<NoteLink>,
+    /// notes linking to the selected note
+    pub backlinks: Vec<Note>,
+    /// which note `links` and `backlinks` belong to
+    links_of: Option<usize>,
+    /// selection over `links` followed by `backlinks`
+    pub link_state: ListState,
+    /// notes we followed a link from, the last one is where going back leads
+    back_stack: Vec<usize>,
+    /// order of the notes inside each notebook
+    pub sort: SortOrder,
+    /// fuzzy score of each note id against the search query, used by `SortOrder::Relevance`
+    relevance: HashMap<usize, usize>,
+    /// whether Up and Down move the selection or scroll the content
+    pub focus: Focus,
+    /// first visible line of the content of each note, kept when moving between notes
+    scroll: HashMap<usize, u16>,
+    /// lines the content pane shows at once, set on every draw
+    pub content_height: u16,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    List,
+    Content,
 }
 
 impl NoteList {
-    pub fn add_note(&mut self, title: String, content: String) {
-        let note = Note::new(title, content);
-        self.notes.push(note);
+    /// loads notes and notebooks again from the service
+    pub fn reload(&mut self, service: &NoteService) -> anyhow::Result<()> {
+        self.notes = service.get_all()?;
+        self.notebooks = service.get_notebooks()?;
+        self.build_rows();
+        self.links_of = None;
+        self.load_links(service)
+    }
+
+    /// loads the links of the selected note, if they aren't loaded yet
+    pub fn load_links(&mut self, service: &NoteService) -> anyhow::Result<()> {
+        let selected = self.get_selected().and_then(|note| note.id);
+        if self.links_of == selected && selected.is_some() {
+            return Ok(());
+        }
+        (self.links, self.backlinks) = match selected {
+            Some(id) => (service.get_links(id)?, service.get_backlinks(id)?),
+            None => (Vec::new(), Vec::new()),
+        };
+        self.links_of = selected;
+        self.link_state.select(None);
+        Ok(())
+    }
+
+    fn next_link(&mut self) {
+        let count = self.links.len() + self.backlinks.len();
+        if count == 0 {
+            return;
+        }
+        let next = self.link_state.selected().map_or(0, |i| (i + 1) % count);
+        self.link_state.select(Some(next));
+    }
+
+    /// opens the note behind the selected link or backlink, broken links go nowhere
+    fn follow_link(&mut self) {
+        let Some(selected) = self.link_state.selected() else {
+            return;
+        };
+        let target = match self.links.get(selected) {
+            Some(link) => link.target_id,
+            None => self
+                .backlinks
+                .get(selected - self.links.len())
+                .and_then(|note| note.id),
+        };
+        let (Some(target), Some(current)) = (target, self.links_of) else {
+            return;
+        };
+        if self.select_note(target) {
+            self.back_stack.push(current);
+        }
+    }
+
+    /// returns to the note we followed the last link from
+    fn go_back(&mut self) {
+        while let Some(id) = self.back_stack.pop() {
+            if self.select_note(id) {
+                return;
+            }
+        }
+    }
+
+    /// selects the note in the tree, expanding the notebooks around it
+    fn select_note(&mut self, id: usize) -> bool {
+        let Some(index) = self.notes.iter().position(|note| note.id == Some(id)) else {
+            return false;
+        };
+        let mut notebook = self.notes[index].notebook_id;
+        while let Some(notebook_id) = notebook {
+            self.collapsed.remove(&notebook_id);
+            notebook = self.notebook(notebook_id).and_then(|nb| nb.parent_id);
+        }
+        self.build_rows();
+        let row = self
+            .rows
+            .iter()
+            .position(|row| matches!(row, TreeRow::Note { index: i, .. } if *i == index));
+        self.state.select(row);
+        row.is_some()
+    }
+
+    fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::List => Focus::Content,
+            Focus::Content => Focus::List,
+        };
+    }
+
+    /// first visible line of the selected note
+    pub fn content_scroll(&self) -> u16 {
+        self.get_selected()
+            .and_then(|note| note.id)
+            .and_then(|id| self.scroll.get(&id).copied())
+            .unwrap_or_default()
+    }
+
+    /// scrolls the selected note to `line`, the ui clamps it to the length of the note
+    pub fn scroll_to(&mut self, line: u16) {
+        if let Some(id) = self.get_selected().and_then(|note| note.id) {
+            self.scroll.insert(id, line);
+        }
+    }
+
+    fn scroll_by(&mut self, lines: i32) {
+        let line = (self.content_scroll() as i32 + lines).clamp(0, u16::MAX as i32);
+        self.scroll_to(line as u16);
+    }
+
+    fn scroll_page(&mut self, pages: i32) {
+        // keep a line of the previous page in sight
+        let page = self.content_height.saturating_sub(1).max(1) as i32;
+        self.scroll_by(pages * page);
+    }
+
+    pub fn set_sort(&mut self, sort: SortOrder) {
+        self.sort = sort;
+        self.resort();
+    }
+
+    fn set_relevance(&mut self, relevance: HashMap<usize, usize>) {
+        self.relevance = relevance;
+        if self.sort == SortOrder::Relevance {
+            self.resort();
+        }
+    }
+
+    fn build_rows(&mut self) {
+        let mut rows = Vec::new();
+        self.push_rows(None, 0, &mut rows);
+        self.rows = rows;
+        if self.rows.is_empty() {
+            self.state.select(None);
+        } else if let Some(selected) = self.state.selected() {
+            self.state
+                .select(Some(selected.min(self.rows.len().saturating_sub(1))));
+        }
     }
+
+    /// sorts the rows again and keeps the same note or notebook selected
+    fn resort(&mut self) {
+        let selected = self.selected_row();
+        self.build_rows();
+        let position = selected.and_then(|selected| {
+            self.rows.iter().position(|&row| match (row, selected) {
+                (TreeRow::Note { index, .. }, TreeRow::Note { index: old, .. }) => index == old,
+                (TreeRow::Notebook { id, .. }, TreeRow::Notebook { id: old, .. }) => id == old,
+                _ => false,
+            })
+        });
+        if position.is_some() {
+            self.state.select(position);
+        }
+    }
+
+    /// most relevant first, ties and notes without a score go by last edit
+    fn compare(&self, a: &Note, b: &Note) -> std::cmp::Ordering {
+        match self.sort {
+            SortOrder::Relevance => {
+                let score = |note: &Note| note.id.and_then(|id| self.relevance.get(&id).copied());
+                score(b)
+                    .cmp(&score(a))
+                    .then_with(|| SortOrder::Updated.compare(a, b))
+            }
+            sort => sort.compare(a, b),
+        }
+    }
+
+    /// pinned notes on top, then notebooks and notes in the order of `parent`
+    fn push_rows(&self, parent: Option<usize>, depth: usize, rows: &mut Vec<TreeRow>) {
+        if parent.is_none() {
+            self.push_notes(|note| note.pinned, depth, rows);
+        }
+        for notebook in self.notebooks.iter().filter(|nb| nb.parent_id == parent) {
+            rows.push(TreeRow::Notebook {
+                id: notebook.id,
+                depth,
+            });
+            if !self.collapsed.contains(&notebook.id) {
+                self.push_rows(Some(notebook.id), depth + 1, rows);
+            }
+        }
+        self.push_notes(
+            |note| !note.pinned && note.notebook_id == parent,
+            depth,
+            rows,
+        );
+    }
+
+    fn push_notes(&self, filter: impl Fn(&Note) -> bool, depth: usize, rows: &mut Vec<TreeRow>) {
+        let mut notes: Vec<usize> = (0..self.notes.len())
+            .filter(|&index| filter(&self.notes[index]))
+            .collect();
+        notes.sort_by(|&a, &b| self.compare(&self.notes[a], &self.notes[b]));
+        rows.extend(
+            notes
+                .into_iter()
+                .map(|index| TreeRow::Note { index, depth }),
+        );
+    }
+
+    pub fn notebook(&self, id: usize) -> Option<&Notebook> {
+        self.notebooks.iter().find(|notebook| notebook.id == id)
+    }
+
     fn next(&mut self) {
-        if self.notes.is_empty() {
+        if self.rows.is_empty() {
             return;
         }
         let i = match self.state.selected() {
             Some(i) => {
-                if i >= self.notes.len() - 1 {
+                if i >= self.rows.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -219,100 +859,331 @@ impl NoteList {
     }
 
     fn previous(&mut self) {
-        if self.notes.is_empty() {
+        if self.rows.is_empty() {
             return;
         }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.notes.len().saturating_sub(1)
+                    self.rows.len().saturating_sub(1)
                 } else {
                     i - 1
                 }
             }
             None => self
                 .last_selected
-                .unwrap_or(self.notes.len().saturating_sub(1)),
+                .unwrap_or(self.rows.len().saturating_sub(1)),
         };
         self.state.select(Some(i));
     }
 
+    fn selected_row(&self) -> Option<TreeRow> {
+        self.state
+            .selected()
+            .and_then(|selected| self.rows.get(selected).copied())
+    }
+
     pub fn get_selected(&self) -> Option<&Note> {
-        if let Some(selected) = self.state.selected() {
-            self.notes.get(selected)
-        } else {
-            None
+        match self.selected_row() {
+            Some(TreeRow::Note { index, .. }) => self.notes.get(index),
+            _ => None,
+        }
+    }
+
+    /// the selected notebook, or the notebook of the selected note
+    pub fn selected_notebook(&self) -> Option<usize> {
+        match self.selected_row() {
+            Some(TreeRow::Notebook { id, .. }) => Some(id),
+            Some(TreeRow::Note { index, .. }) => self.notes[index].notebook_id,
+            None => None,
         }
     }
 
-    /// adds note
-    pub fn get_user_note(&mut self) {
-        let note = match edit_with_vim(None) {
-            Ok(note) => note,
-            Err(e) => panic!("failed to parse note with error {e}"),
+    /// expands or collapses the selected notebook
+    fn toggle_selected_notebook(&mut self) {
+        if let Some(TreeRow::Notebook { id, .. }) = self.selected_row() {
+            if !self.collapsed.remove(&id) {
+                self.collapsed.insert(id);
+            }
+            self.build_rows();
+        }
+    }
+
+    /// adds note to the selected notebook
+    pub fn get_user_note(&mut self, service: &mut NoteService, editor: &str) -> anyhow::Result<()> {
+        let note =
+            edit_with(editor, None).map_err(|err| anyhow::anyhow!("write the note: {err}"))?;
+        let Some(note) = note else {
+            return Ok(());
         };
         let note = note.trim();
         if note.is_empty() {
-            return;
+            return Ok(());
         }
-        let title_end_index = note.find("\n").unwrap_or(note.len());
-        let title = note.get(..title_end_index).unwrap_or_default().to_string();
-        let content = note.get(title_end_index..).unwrap_or_default().to_string();
-        self.add_note(title, content);
-    }
-
-    /// if any note is selected, it deletes it!
-    pub fn delete_note(&mut self) {
-        if let Some(index) = self.state.selected() {
-            self.notes.remove(self.state.selected().unwrap_or_default());
-            // set note state after deletion
-            if self.notes.is_empty() {
-                self.state.select(None);
-            } else if self.notes.len() == index {
-                self.previous();
-            }
+        let (title, content) = split_title(note);
+        let mut note = Note::new(None, title, content);
+        note.notebook_id = self.selected_notebook();
+        service.save_note(note)?;
+        self.reload(service)
+    }
+
+    /// creates a notebook inside the selected one, the first line is its name
+    pub fn get_user_notebook(&mut self, service: &NoteService, editor: &str) -> anyhow::Result<()> {
+        let name = edit_with(editor, None)
+            .map_err(|err| anyhow::anyhow!("read the notebook name: {err}"))?;
+        let Some(name) = name else {
+            return Ok(());
+        };
+        let name = name.lines().next().unwrap_or_default().trim();
+        if name.is_empty() {
+            return Ok(());
+        }
+        let parent = self.selected_notebook();
+        service.create_notebook(name, parent)?;
+        if let Some(parent) = parent {
+            self.collapsed.remove(&parent);
+        }
+        self.reload(service)
+    }
+
+    /// moves the note to the trash
+    pub fn delete_note(&mut self, service: &mut NoteService, id: usize) -> anyhow::Result<()> {
+        service.delete_note(id)?;
+        if self.cut == Some(id) {
+            self.cut = None;
         }
+        // set note state after deletion
+        self.reload(service)
     }
 
-    pub fn edit_note(&mut self) {
+    pub fn edit_note(&mut self, service: &mut NoteService, editor: &str) -> anyhow::Result<()> {
         let current_note = match self.get_selected() {
             Some(note) => note,
-            None => return,
+            None => return Ok(()),
+        };
+        let text = format!("{}\n{}", current_note.title, current_note.text);
+        let note = edit_with(editor, Some(text.as_str()))
+            .map_err(|err| anyhow::anyhow!("edit the note: {err}"))?;
+        let Some(note) = note else {
+            return Ok(());
+        };
+        let (title, content) = split_title(&note);
+        let mut note = current_note.clone();
+        note.title = title;
+        note.text = content;
+        service.save_note(note)?;
+        self.reload(service)
+    }
+
+    /// marks the selected note to be moved by the next paste
+    fn cut_note(&mut self) {
+        self.cut = self.get_selected().and_then(|note| note.id);
+    }
+
+    /// pins the selected note to the top of the list, or unpins it
+    fn toggle_pin(&mut self, service: &NoteService) -> anyhow::Result<()> {
+        let Some((id, pinned)) = self
+            .get_selected()
+            .and_then(|note| Some((note.id?, note.pinned)))
+        else {
+            return Ok(());
         };
-        let text = format!("{}\n{}", current_note.title, current_note.content);
-        let note = match edit_with_vim(Some(text.as_str())) {
-            Ok(note) => note,
-            Err(e) => panic!("failed to parse note with error {e}"),
+        service.pin_note(id, !pinned)?;
+        self.reload(service)?;
+        self.select_note(id);
+        Ok(())
+    }
+
+    /// moves the cut note into the selected notebook
+    fn paste_note(&mut self, service: &mut NoteService) -> anyhow::Result<()> {
+        let Some(id) = self.cut.take() else {
+            return Ok(());
         };
-        let title_end_index = note.find("\n").unwrap_or(note.len());
-        let title = note.get(..title_end_index).unwrap_or_default().to_string();
-        let content = note.get(title_end_index..).unwrap_or_default().to_string();
-        let mut note = Note::new(title, content);
-        mem::swap(&mut self.notes[self.state.selected().unwrap()], &mut note); // :)
+        service.move_note(id, self.selected_notebook())?;
+        self.reload(service)
     }
 }
 
+/// notes in the trash, the most recently deleted first
 #[derive(Debug, Default)]
-pub struct Note {
-    id: Option<usize>,
-    pub title: String,
-    pub content: String,
+pub struct TrashList {
+    pub state: ListState,
+    pub notes: Vec<Note>,
+}
+
+impl TrashList {
+    pub fn reload(&mut self, service: &NoteService) -> anyhow::Result<()> {
+        self.notes = service.get_trash()?;
+        if self.notes.is_empty() {
+            self.state.select(None);
+        } else if let Some(selected) = self.state.selected() {
+            self.state.select(Some(selected.min(self.notes.len() - 1)));
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) {
+        if self.notes.is_empty() {
+            return;
+        }
+        let i = self
+            .state
+            .selected()
+            .map_or(0, |i| (i + 1) % self.notes.len());
+        self.state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        if self.notes.is_empty() {
+            return;
+        }
+        let i = self
+            .state
+            .selected()
+            .map_or(0, |i| i.checked_sub(1).unwrap_or(self.notes.len() - 1));
+        self.state.select(Some(i));
+    }
+
+    pub fn get_selected(&self) -> Option<&Note> {
+        self.state
+            .selected()
+            .and_then(|selected| self.notes.get(selected))
+    }
 }
 
-impl Note {
-    pub fn new(title: String, content: String) -> Note {
-        Note {
-            id: None,
-            title,
-            content,
+/// whether the key types its character, keys held with ctrl or alt are commands
+fn is_typed(event: KeyEvent) -> bool {
+    !event
+        .modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+}
+
+/// the profiles of the config, the open one selected
+pub struct Vaults {
+    pub names: Vec<String>,
+    pub state: ListState,
+}
+
+impl Vaults {
+    fn new(config: &Config) -> Vaults {
+        let names: Vec<String> = config.profiles.keys().cloned().collect();
+        let open = names
+            .iter()
+            .position(|name| Some(name.as_str()) == config.profile.as_deref());
+        Vaults {
+            names,
+            state: ListState::default().with_selected(open.or(Some(0))),
         }
     }
+
+    fn next(&mut self) {
+        let last = self.names.len().saturating_sub(1);
+        let i = self.state.selected().map_or(0, |i| (i + 1).min(last));
+        self.state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        let i = self.state.selected().map_or(0, |i| i.saturating_sub(1));
+        self.state.select(Some(i));
+    }
+
+    fn selected(&self) -> Option<&str> {
+        self.state
+            .selected()
+            .and_then(|selected| self.names.get(selected))
+            .map(String::as_str)
+    }
+}
+
+/// passphrase of an encrypted vault as it is typed, shown masked
+pub struct Unlock {
+    /// profile that opens once unlocked, `None` for the one the config selects
+    pub profile: Option<String>,
+    pub passphrase: String,
+    /// why the last passphrase didn't open the vault
+    pub error: Option<String>,
+}
+
+/// what a key press did to the passphrase
+pub enum UnlockKey {
+    Typed,
+    Submit(String),
+    Cancel,
+}
+
+impl Unlock {
+    pub fn new(profile: Option<String>) -> Unlock {
+        Unlock {
+            profile,
+            passphrase: String::new(),
+            error: None,
+        }
+    }
+
+    /// the passphrase is taken on enter and typed again after a wrong one. ctrl-c cancels like
+    /// esc, other keys held with ctrl or alt aren't typed
+    pub fn handle_key(&mut self, event: KeyEvent) -> UnlockKey {
+        match event.code {
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return UnlockKey::Cancel
+            }
+            KeyCode::Char(key) if is_typed(event) => self.passphrase.push(key),
+            KeyCode::Backspace => {
+                self.passphrase.pop();
+            }
+            KeyCode::Enter => return UnlockKey::Submit(std::mem::take(&mut self.passphrase)),
+            KeyCode::Esc => return UnlockKey::Cancel,
+            _ => (),
+        }
+        UnlockKey::Typed
+    }
+}
+
+pub struct History {
+    pub revisions: Vec<Revision>,
+    pub state: ListState,
+}
+
+impl History {
+    fn next(&mut self) {
+        let last = self.revisions.len().saturating_sub(1);
+        let i = self.state.selected().map_or(0, |i| (i + 1).min(last));
+        self.state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        let i = self.state.selected().map_or(0, |i| i.saturating_sub(1));
+        self.state.select(Some(i));
+    }
+
+    pub fn selected(&self) -> Option<&Revision> {
+        self.state
+            .selected()
+            .and_then(|selected| self.revisions.get(selected))
+    }
+
+    /// the revision before the selected one, what the diff compares against
+    pub fn before_selected(&self) -> Option<&Revision> {
+        self.state
+            .selected()
+            .and_then(|selected| selected.checked_sub(1))
+            .and_then(|before| self.revisions.get(before))
+    }
+}
+
+/// first line of the text is the title, the rest after its newline is the content
+fn split_title(text: &str) -> (String, String) {
+    match text.split_once('\n') {
+        Some((title, content)) => (title.to_string(), content.to_string()),
+        None => (text.to_string(), String::new()),
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
 pub enum EditorMode {
     Add,
     Edit,
+    Notebook,
     None,
 }
 
@@ -326,7 +1197,7 @@ impl Matcher {
         notes
             .iter()
             .map(|&note| {
-                self.match_single(text, &format!("{} {}", note.title, note.content)[..])
+                self.match_single(text, &format!("{} {}", note.title, note.text)[..])
                     .unwrap_or(0)
             })
             .collect()
@@ -343,7 +1214,97 @@ impl Matcher {
 
 #[cfg(test)]
 mod tests {
-    use super::Matcher;
+    use super::{edit_with, split_title, App, EditorMode, InputMode, Matcher, Unlock, UnlockKey};
+    use crate::{
+        config::Config, memory_store::MemoryStore, pool::RepositoryPool, repository::Note,
+        service::NoteService,
+    };
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn modified_keys_arent_typed() {
+        let config = Config {
+            keys: "no-such-keys.toml".into(),
+            ..Config::default()
+        };
+        let mut app = App::new(NoteService::new(MemoryStore::new()), &config).unwrap();
+        app.input_mode = InputMode::Insert;
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        app.handle_press(key(KeyCode::Char('a'), KeyModifiers::NONE))
+            .unwrap();
+        app.handle_press(key(KeyCode::Char('b'), KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.search_query, "a");
+        assert!(!app.should_quit);
+        app.handle_press(key(KeyCode::Char('c'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.search_query, "a");
+        assert!(app.should_quit);
+
+        let mut unlock = Unlock::new(None);
+        unlock.handle_key(key(KeyCode::Char('a'), KeyModifiers::NONE));
+        unlock.handle_key(key(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(unlock.passphrase, "a");
+        let cancel = unlock.handle_key(key(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(matches!(cancel, UnlockKey::Cancel));
+    }
+
+    #[test]
+    fn editing_without_changes_keeps_the_note() {
+        for (title, text) in [
+            ("Plan", "- ship it\n\nthen rest\n"),
+            ("Empty", ""),
+            ("a", "\n"),
+        ] {
+            let edited = edit_with("true", Some(&format!("{title}\n{text}")))
+                .unwrap()
+                .unwrap();
+            assert_eq!(split_title(&edited), (title.to_string(), text.to_string()));
+        }
+    }
+
+    #[test]
+    fn unchanged_edits_arent_revisions() {
+        let config = Config {
+            keys: "no-such-keys.toml".into(),
+            editor: "true".to_string(),
+            ..Config::default()
+        };
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let id = service
+            .save_note(Note::new(
+                None,
+                "Plan".to_string(),
+                "- ship it\n".to_string(),
+            ))
+            .unwrap();
+        let before = service.get_note(id).unwrap();
+        let mut app = App::new(service, &config).unwrap();
+        assert!(app.note_list.select_note(id));
+        app.editor_mode = EditorMode::Edit;
+        app.run_editor().unwrap();
+        assert_eq!(app.service.get_note(id).unwrap(), before);
+        assert_eq!(app.service.get_history(id).unwrap().len(), 1);
+        assert_eq!(app.undo, None);
+    }
+
+    #[test]
+    fn failed_editors_change_nothing() {
+        assert_eq!(edit_with("false", Some("a")).unwrap(), None);
+        let mut config = Config {
+            keys: "no-such-keys.toml".into(),
+            editor: "no-such-editor".to_string(),
+            ..Config::default()
+        };
+        let mut app = App::new(NoteService::new(MemoryStore::new()), &config).unwrap();
+        app.editor_mode = EditorMode::Add;
+        assert!(app.run_editor().is_err());
+        config.editor = "false".to_string();
+        app.config = config;
+        app.editor_mode = EditorMode::Add;
+        app.run_editor().unwrap();
+        assert!(app.service.get_all().unwrap().is_empty());
+    }
 
     #[test]
     fn matcher() {
@@ -0,0 +1,270 @@
+// the whole store in one json file, for backups and moving notes between databases.
+// nothing is left out: notebooks, notes in and out of the trash with their tags, links
+// and every revision. a file name ending in `.gz` is gzipped
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+/// bumped whenever the shape of the archive changes
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    /// embedding engine the notes were indexed in, they need indexing again elsewhere
+    pub ai_engine: Option<String>,
+    pub notebooks: Vec<ArchiveNotebook>,
+    pub notes: Vec<ArchiveNote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveNotebook {
+    pub id: usize,
+    pub name: String,
+    pub parent_id: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveNote {
+    pub id: usize,
+    pub title: String,
+    pub text: String,
+    pub notebook_id: Option<usize>,
+    pub pinned: bool,
+    pub tags: Vec<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub deleted_at: Option<String>,
+    /// what the links resolved to, restoring resolves them again from the text
+    pub links: Vec<ArchiveLink>,
+    /// oldest first
+    pub revisions: Vec<ArchiveRevision>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveLink {
+    pub target: String,
+    pub target_id: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveRevision {
+    pub revision: usize,
+    pub title: String,
+    pub text: String,
+    pub created_at: String,
+}
+
+/// what restoring does with a note whose id is already taken
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// keep the stored note
+    #[default]
+    Skip,
+    /// replace the stored note and its revisions
+    Overwrite,
+    /// add the archived note under a new id
+    KeepBoth,
+}
+
+impl Conflict {
+    pub const ALL: [Conflict; 3] = [Conflict::Skip, Conflict::Overwrite, Conflict::KeepBoth];
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Conflict::Skip => "skip",
+            Conflict::Overwrite => "overwrite",
+            Conflict::KeepBoth => "keep-both",
+        })
+    }
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Conflict::ALL
+            .into_iter()
+            .find(|conflict| conflict.to_string() == s)
+            .ok_or_else(|| {
+                format!("unknown conflict strategy {s:?}, use skip, overwrite or keep-both")
+            })
+    }
+}
+
+/// what an import did
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} new, {} updated, {} skipped",
+            self.created, self.updated, self.skipped
+        )
+    }
+}
+
+/// what an import from another app did and what it couldn't convert
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConversionReport {
+    pub notes: usize,
+    pub attachments: usize,
+    /// one line per problem, starting with the file or note it is about
+    pub problems: Vec<String>,
+}
+
+impl Display for ConversionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} notes, {} attachments, {} problems",
+            self.notes,
+            self.attachments,
+            self.problems.len()
+        )
+    }
+}
+
+impl Archive {
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("create archive {}", path.display()))?;
+        let mut writer: Box<dyn Write> = if is_gzip_path(path) {
+            Box::new(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            Box::new(BufWriter::new(file))
+        };
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// reads a plain or gzipped archive, whatever its file name says
+    pub fn read(path: &Path) -> anyhow::Result<Archive> {
+        let mut reader = BufReader::new(
+            File::open(path).with_context(|| format!("open archive {}", path.display()))?,
+        );
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let archive: Archive = if bytes.starts_with(&[0x1f, 0x8b]) {
+            serde_json::from_reader(GzDecoder::new(bytes.as_slice()))
+        } else {
+            serde_json::from_slice(&bytes)
+        }
+        .with_context(|| format!("archive {}", path.display()))?;
+        if archive.version != ARCHIVE_VERSION {
+            bail!(
+                "archive {} has version {}, this build reads version {ARCHIVE_VERSION}",
+                path.display(),
+                archive.version
+            );
+        }
+        Ok(archive)
+    }
+}
+
+fn is_gzip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Archive, Conflict, ImportReport};
+    use crate::{pool::RepositoryPool, repository::Note, service::NoteService};
+
+    fn store() -> NoteService {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let work = service.create_notebook("work", None).unwrap();
+        let projects = service.create_notebook("projects", Some(work)).unwrap();
+        let mut plan = Note::new(None, "plan".to_string(), "see [[ideas]]".to_string());
+        plan.notebook_id = Some(projects);
+        plan.tags = vec!["todo".to_string()];
+        let id = service.save_note(plan).unwrap();
+        let mut plan = service.get_note(id).unwrap();
+        plan.text = "see [[ideas]] and ship".to_string();
+        service.save_note(plan).unwrap();
+        service
+            .save_note(Note::new(None, "ideas".to_string(), "more".to_string()))
+            .unwrap();
+        let old = service
+            .save_note(Note::new(None, "old".to_string(), "gone".to_string()))
+            .unwrap();
+        service.delete_note(old).unwrap();
+        service
+    }
+
+    #[test]
+    fn round_trip() {
+        let service = store();
+        let archive = service.export_archive().unwrap();
+        assert_eq!(archive.notes.len(), 3);
+        assert_eq!(archive.notes[0].revisions.len(), 2);
+        assert_eq!(archive.notes[0].links[0].target_id, Some(2));
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["backup.json", "backup.json.gz"] {
+            let path = dir.path().join(name);
+            archive.write(&path).unwrap();
+            assert_eq!(Archive::read(&path).unwrap(), archive);
+        }
+
+        // an empty store gets everything back as it was
+        let mut other = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let report = other.import_archive(&archive, Conflict::Skip).unwrap();
+        assert_eq!(report.to_string(), "3 new, 0 updated, 0 skipped");
+        assert_eq!(other.export_archive().unwrap(), archive);
+    }
+
+    #[test]
+    fn conflicts() {
+        let archive = store().export_archive().unwrap();
+        let mut service = store();
+        let mut plan = service.get_note(1).unwrap();
+        plan.text = "changed".to_string();
+        service.save_note(plan).unwrap();
+
+        let report = service.import_archive(&archive, Conflict::Skip).unwrap();
+        assert_eq!(report.skipped, 3);
+        assert_eq!(service.get_note(1).unwrap().text, "changed");
+
+        let report = service
+            .import_archive(&archive, Conflict::Overwrite)
+            .unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                created: 0,
+                updated: 3,
+                skipped: 0
+            }
+        );
+        assert_eq!(service.export_archive().unwrap(), archive);
+
+        let report = service
+            .import_archive(&archive, Conflict::KeepBoth)
+            .unwrap();
+        assert_eq!(report.created, 3);
+        assert_eq!(service.get_all().unwrap().len(), 4);
+        assert_eq!(service.get_trash().unwrap().len(), 2);
+        // notebooks are found by their path instead of being made twice
+        assert_eq!(service.get_notebooks().unwrap().len(), 2);
+        assert!("keep-both".parse::<Conflict>().is_ok());
+    }
+}
@@ -1,29 +1,37 @@
-use anyhow;
 use crossterm::{
     event::{self, Event, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use ratatui::prelude::*;
+use ratatui::{prelude::*, widgets::Block};
 use std::{
     io::{stdout, Stdout},
     time::{Duration, Instant},
 };
 
 use crate::{
-    app::{App, EditorMode},
+    app::{App, EditorMode, Unlock, UnlockKey},
+    config::Config,
+    service::NoteService,
     ui,
 };
 
-pub fn run(tick_rate: Duration) -> anyhow::Result<()> {
+pub fn run(config: &Config) -> anyhow::Result<()> {
     // create app and run it
-    let mut app = App::new();
-    for i in 0..100 {
-        let title = format!("title {i}");
-        let content = format!("content {i}").repeat(100);
-        app.note_list.add_note(title, content);
-    }
-    let res = run_app(app, tick_rate);
+    let mut service = if NoteService::needs_passphrase(config)? {
+        let mut terminal = init_terminal()?;
+        let service = unlock_loop(&mut terminal, config);
+        clean_terminal(&mut terminal)?;
+        match service? {
+            Some(service) => service,
+            None => return Ok(()),
+        }
+    } else {
+        NoteService::open(config)?
+    };
+    service.purge_expired()?;
+    let app = App::new(service, config)?;
+    let res = run_app(app, Duration::from_millis(config.tick_rate));
     if let Err(err) = res {
         println!("{err:?}");
     }
@@ -31,26 +39,66 @@ pub fn run(tick_rate: Duration) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// asks for the passphrase of the encrypted vault until it opens, `None` when given up
+fn unlock_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    config: &Config,
+) -> anyhow::Result<Option<NoteService>> {
+    let theme = config.theme()?;
+    let mut unlock = Unlock::new(None);
+    loop {
+        terminal.draw(|f| {
+            f.render_widget(Block::new().bg(theme.background), f.size());
+            ui::render_unlock(f, &unlock, &theme, f.size());
+        })?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match unlock.handle_key(key) {
+            UnlockKey::Typed => (),
+            UnlockKey::Cancel => return Ok(None),
+            UnlockKey::Submit(passphrase) => {
+                match NoteService::open_with(config, Some(&passphrase)) {
+                    Ok(service) => return Ok(Some(service)),
+                    Err(err) => unlock.error = Some(err.to_string()),
+                }
+            }
+        }
+    }
+}
+
 fn run_app(mut app: App, tick_rate: Duration) -> anyhow::Result<()> {
     let mut terminal = init_terminal()?;
+    // give the terminal back even when the app fails
+    let res = event_loop(&mut terminal, &mut app, tick_rate);
+    clean_terminal(&mut terminal)?;
+    res
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &mut App,
+    tick_rate: Duration,
+) -> anyhow::Result<()> {
     let mut last_tick = Instant::now();
 
     loop {
-        terminal.draw(|f| ui::draw(f, &mut app))?;
+        terminal.draw(|f| ui::draw(f, app))?;
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if crossterm::event::poll(timeout).expect("polling event!") {
             if let Event::Key(key) = event::read().expect("read key event!") {
                 if key.kind == KeyEventKind::Press {
-                    app.handle_press(key);
+                    // a failed action is shown in the footer, only the terminal ends the app
+                    app.status = app.handle_press(key).err().map(|err| format!("{err:#}"));
                     if app.editor_mode != EditorMode::None {
-                        clean_terminal(&mut terminal)?;
-                        if app.editor_mode == EditorMode::Add {
-                            app.note_list.get_user_note();
-                        } else {
-                            app.note_list.edit_note();
+                        clean_terminal(terminal)?;
+                        if let Err(err) = app.run_editor() {
+                            app.status = Some(format!("{err:#}"));
                         }
-                        app.editor_mode = EditorMode::None;
-                        terminal = init_terminal()?;
+                        *terminal = init_terminal()?;
                     }
                     if app.should_quit {
                         break;
@@ -64,7 +112,7 @@ fn run_app(mut app: App, tick_rate: Duration) -> anyhow::Result<()> {
         app.logo_position += 5; // move main logo forward!
     }
 
-    clean_terminal(&mut terminal)
+    Ok(())
 }
 fn init_terminal() -> anyhow::Result<Terminal<CrosstermBackend<Stdout>>> {
     // setup terminal
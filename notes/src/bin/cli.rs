@@ -1,24 +1,629 @@
+use std::{io::IsTerminal, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context};
 use argh::FromArgs;
-use notes::Repository;
+use crossterm::style::Stylize;
+use notes::{
+    diff_lines, Archive, Config, Conflict, DiffLine, NoteService, Notebook, Overrides,
+    RepositoryPool, SortOrder, StoreKind,
+};
 
-/// Demo
+/// Manage notes from the terminal
 #[derive(Debug, FromArgs)]
 struct Cli {
-    /// time in ms between two ticks.
+    /// config file, $XDG_CONFIG_HOME/notes/config.toml by default
+    #[argh(option)]
+    config: Option<PathBuf>,
+    /// profile of the config to open, --db, --store and --ai-engine replace its settings
+    #[argh(option)]
+    profile: Option<String>,
+    /// sqlite database of the notes, $XDG_DATA_HOME/notes/notes.db by default, or the
+    /// directory of a markdown store
+    #[argh(option)]
+    db: Option<PathBuf>,
+    /// what --db is: sqlite (default), markdown for a directory of markdown files or git
+    /// for one that is committed to git
+    #[argh(option)]
+    store: Option<StoreKind>,
+    /// url of the ai embedding engine
+    #[argh(option)]
+    ai_engine: Option<String>,
+    /// days deleted notes stay in the trash
+    #[argh(option)]
+    trash_days: Option<u32>,
+    #[argh(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    List(ListCommand),
+    Show(ShowCommand),
+    Search(SearchCommand),
+    Notebooks(NotebooksCommand),
+    NewNotebook(NewNotebookCommand),
+    Move(MoveCommand),
+    Links(LinksCommand),
+    BrokenLinks(BrokenLinksCommand),
+    Rename(RenameCommand),
+    History(HistoryCommand),
+    Restore(RestoreCommand),
+    Delete(DeleteCommand),
+    Trash(TrashCommand),
+    Untrash(UntrashCommand),
+    Purge(PurgeCommand),
+    Pin(PinCommand),
+    Unpin(UnpinCommand),
+    Tag(TagCommand),
+    Export(ExportCommand),
+    Import(ImportCommand),
+    Sync(SyncCommand),
+    Encrypt(EncryptCommand),
+    Decrypt(DecryptCommand),
+}
+
+/// list notes
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "list")]
+struct ListCommand {
+    /// only list notes inside this notebook path, like work/projects
+    #[argh(option)]
+    notebook: Option<String>,
+    /// order of the notes: updated (default), created or title
+    #[argh(option, default = "SortOrder::Updated")]
+    sort: SortOrder,
+}
+
+/// print a note, its markdown is rendered when printing to a terminal
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "show")]
+struct ShowCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+    /// print the markdown as written
+    #[argh(switch)]
+    raw: bool,
+    /// colours of the rendered markdown, a built-in theme or a toml file
+    #[argh(option)]
+    theme: Option<String>,
+}
+
+/// search notes
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "search")]
+struct SearchCommand {
+    /// text to search for
     #[argh(positional)]
     search_query: String,
+    /// only search notes inside this notebook path, like work/projects
+    #[argh(option)]
+    notebook: Option<String>,
+    /// use the ai engine for a semantic search
+    #[argh(switch)]
+    ai: bool,
+    /// order of the results: relevance (default), updated, created or title
+    #[argh(option, default = "SortOrder::Relevance")]
+    sort: SortOrder,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+/// show the notebook tree
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "notebooks")]
+struct NotebooksCommand {}
+
+/// create a notebook
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "new-notebook")]
+struct NewNotebookCommand {
+    /// name of the notebook
+    #[argh(positional)]
+    name: String,
+    /// path of the notebook to create it in
+    #[argh(option)]
+    parent: Option<String>,
+}
+
+/// move a note into a notebook
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "move")]
+struct MoveCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+    /// path of the target notebook, moves the note to the top level when missing
+    #[argh(positional)]
+    notebook: Option<String>,
+}
+
+/// show the links of a note and the notes linking to it
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "links")]
+struct LinksCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+}
+
+/// list links pointing to notes that don't exist
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "broken-links")]
+struct BrokenLinksCommand {}
+
+/// change the title of a note and rewrite the links to it
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rename")]
+struct RenameCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+    /// new title of the note
+    #[argh(positional)]
+    title: String,
+    /// only show the notes whose links would be rewritten
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+/// list the revisions of a note
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "history")]
+struct HistoryCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+    /// show the lines this revision changed instead of the list
+    #[argh(option)]
+    diff: Option<usize>,
+}
+
+/// bring back an old revision of a note
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "restore")]
+struct RestoreCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+    /// revision to restore
+    #[argh(positional)]
+    revision: usize,
+}
+
+/// move a note to the trash
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "delete")]
+struct DeleteCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+}
+
+/// list the notes in the trash
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "trash")]
+struct TrashCommand {}
+
+/// take a note out of the trash
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "untrash")]
+struct UntrashCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+}
+
+/// delete notes in the trash for good
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "purge")]
+struct PurgeCommand {
+    /// id of the note, every note in the trash with --all
+    #[argh(positional)]
+    note_id: Option<usize>,
+    /// empty the whole trash
+    #[argh(switch)]
+    all: bool,
+}
+
+/// keep a note at the top of the list
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "pin")]
+struct PinCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+}
+
+/// stop keeping a note at the top of the list
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "unpin")]
+struct UnpinCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+}
+
+/// replace the tags of a note, no tags removes them all
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "tag")]
+struct TagCommand {
+    /// id of the note
+    #[argh(positional)]
+    note_id: usize,
+    /// tags of the note, a leading # is dropped
+    #[argh(positional)]
+    tags: Vec<String>,
+}
+
+/// write every note to a directory or an archive
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "export")]
+struct ExportCommand {
+    /// format of the export: markdown (default), one file per note with front matter, or
+    /// json, the whole store with the trash and history in one file, gzipped for .gz
+    #[argh(option, default = "ExportFormat::Markdown")]
+    format: ExportFormat,
+    /// directory or file to write to, created when missing
+    #[argh(positional)]
+    path: PathBuf,
+}
+
+/// read notes written by export, notes whose id exists are updated
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "import")]
+struct ImportCommand {
+    /// format of the import: markdown, json, obsidian, enex or keep. a directory is read
+    /// as markdown, an .enex file as enex and other files as json by default
+    #[argh(option)]
+    format: Option<ImportFormat>,
+    /// where the attachments of an obsidian vault are copied, `attachments` next to the
+    /// database by default
+    #[argh(option)]
+    attachments: Option<PathBuf>,
+    /// for json archives, what happens to notes whose id is taken: skip (default),
+    /// overwrite or keep-both
+    #[argh(option, default = "Conflict::Skip")]
+    on_conflict: Conflict,
+    /// directory of markdown files or an obsidian vault, their folders become notebooks,
+    /// a json archive, an evernote export or the Keep folder of a google takeout
+    #[argh(positional)]
+    path: PathBuf,
+}
+
+/// commit the notes of a git store, rebase them on the remote and push them
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "sync")]
+struct SyncCommand {
+    /// remote to pull from and push to, the git_remote of the config by default
+    #[argh(option)]
+    remote: Option<String>,
+}
+
+/// seal the titles and texts of a sqlite vault with a passphrase, $NOTES_PASSPHRASE or asked
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "encrypt")]
+struct EncryptCommand {}
+
+/// store the notes of an encrypted vault as plain text again
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "decrypt")]
+struct DecryptCommand {}
+
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown export format {s:?}, use markdown or json")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ImportFormat {
+    Markdown,
+    Json,
+    Obsidian,
+    Enex,
+    Keep,
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(ImportFormat::Markdown),
+            "json" => Ok(ImportFormat::Json),
+            "obsidian" => Ok(ImportFormat::Obsidian),
+            "enex" | "evernote" => Ok(ImportFormat::Enex),
+            "keep" => Ok(ImportFormat::Keep),
+            _ => Err(format!(
+                "unknown import format {s:?}, use markdown, json, obsidian, enex (or evernote) or keep"
+            )),
+        }
+    }
+}
+
+// the store calls block, so main runs outside tokio and only semantic search enters a runtime
+fn main() -> anyhow::Result<()> {
     let cli: Cli = argh::from_env();
-    let mut reposity = Repository::new("notes.db")?;
-    // reposity.initialize_db()?;
-    // reposity.insert_test_notes_ai().await?;
-    let service = notes::NoteService::new(reposity);
-    // let founed_notes = service.search_notes(cli.search_query.as_ref());
-    // let search_res = notes::search(cli.search_query.as_ref()).await?;
-    let search_res = service.search_ai(cli.search_query.as_ref()).await?;
-    dbg!(search_res);
+    let mut config = Config::load(cli.config.as_deref())?;
+    config.override_with(Overrides {
+        db: cli.db,
+        store: cli.store,
+        ai_engine: cli.ai_engine,
+    });
+    if let Some(days) = cli.trash_days {
+        config.trash_days = days;
+    }
+    config.select_profile(cli.profile.as_deref())?;
+    match cli.command {
+        Command::Encrypt(_) | Command::Decrypt(_) if config.store != StoreKind::Sqlite => {
+            bail!("only sqlite vaults can be encrypted");
+        }
+        Command::Encrypt(_) => {
+            let pool = RepositoryPool::open(&config.db)?;
+            if notes::is_encrypted(&pool)? {
+                bail!("the vault is already encrypted");
+            }
+            let passphrase = passphrase("new passphrase: ")?;
+            if std::env::var_os("NOTES_PASSPHRASE").is_none()
+                && passphrase != passphrase_prompt("repeat it: ")?
+            {
+                bail!("the passphrases don't match");
+            }
+            let count = notes::encrypt_vault(&pool, &passphrase)?;
+            println!("encrypted {count} notes");
+            return Ok(());
+        }
+        Command::Decrypt(_) => {
+            let pool = RepositoryPool::open(&config.db)?;
+            let count = notes::decrypt_vault(&pool, &passphrase("passphrase: ")?)?;
+            println!("decrypted {count} notes");
+            return Ok(());
+        }
+        _ => {}
+    }
+    let mut service = if NoteService::needs_passphrase(&config)? {
+        NoteService::open_with(&config, Some(&passphrase("passphrase: ")?))?
+    } else {
+        NoteService::open(&config)?
+    };
+    service.purge_expired()?;
+    match cli.command {
+        Command::List(command) => {
+            let notebook = find_notebook(&service, command.notebook.as_deref())?;
+            for note in service.get_notes_in(notebook, command.sort)? {
+                println!(
+                    "{}\t{}\t{}",
+                    note.id.unwrap_or_default(),
+                    note.updated_at.unwrap_or_default(),
+                    note.title
+                );
+            }
+        }
+        Command::Show(command) => {
+            let note = service.get_note(command.note_id)?;
+            if command.raw || !std::io::stdout().is_terminal() {
+                println!("{}\n\n{}", note.title, note.text);
+            } else {
+                println!("{}", note.title.as_str().bold());
+                if !note.tags.is_empty() {
+                    let tags: Vec<String> = note.tags.iter().map(|tag| format!("#{tag}")).collect();
+                    println!("{}", tags.join(" ").dark_grey());
+                }
+                println!();
+                if let Some(theme) = command.theme {
+                    config.theme = theme;
+                }
+                let theme = config.theme()?;
+                print!("{}", notes::render_ansi(&note.text, &theme));
+            }
+        }
+        Command::Search(command) => {
+            let notebook = find_notebook(&service, command.notebook.as_deref())?;
+            let query = command.search_query.as_str();
+            if command.ai {
+                let runtime = tokio::runtime::Runtime::new().context("start tokio")?;
+                let search = service.search_ai(query, notebook, command.sort);
+                for (note, score) in runtime.block_on(search)? {
+                    println!(
+                        "{}\t{score:.2}\t{}",
+                        note.id.unwrap_or_default(),
+                        note.title
+                    );
+                }
+            } else {
+                for (note, score) in service.search_notes(query, notebook, command.sort)? {
+                    println!("{}\t{score}\t{}", note.id.unwrap_or_default(), note.title);
+                }
+            }
+        }
+        Command::Notebooks(_) => {
+            let notebooks = service.get_notebooks()?;
+            print_notebooks(&notebooks, None, 0);
+        }
+        Command::NewNotebook(command) => {
+            let parent = find_notebook(&service, command.parent.as_deref())?;
+            let id = service.create_notebook(&command.name, parent)?;
+            println!("{id}");
+        }
+        Command::Move(command) => {
+            let notebook = find_notebook(&service, command.notebook.as_deref())?;
+            service.move_note(command.note_id, notebook)?;
+        }
+        Command::Links(command) => {
+            for link in service.get_links(command.note_id)? {
+                match link.target_id {
+                    Some(id) => println!("-> {id}\t{}", link.target),
+                    None => println!("-> broken\t{}", link.target),
+                }
+            }
+            for note in service.get_backlinks(command.note_id)? {
+                println!("<- {}\t{}", note.id.unwrap_or_default(), note.title);
+            }
+        }
+        Command::BrokenLinks(_) => {
+            for link in service.get_broken_links()? {
+                println!("{}\t[[{}]]", link.source_id, link.target);
+            }
+        }
+        Command::Rename(command) => {
+            for note in service.preview_rename(command.note_id, &command.title)? {
+                println!("{}\t{}", note.id.unwrap_or_default(), note.title);
+            }
+            if !command.dry_run {
+                let mut note = service.get_note(command.note_id)?;
+                note.title = command.title;
+                service.save_note(note)?;
+            }
+        }
+        Command::History(command) => {
+            let history = service.get_history(command.note_id)?;
+            match command.diff {
+                Some(revision) => {
+                    let index = history
+                        .iter()
+                        .position(|rev| rev.revision == revision)
+                        .with_context(|| format!("revision {revision} doesn't exist"))?;
+                    let old = index
+                        .checked_sub(1)
+                        .map(|before| history[before].content())
+                        .unwrap_or_default();
+                    for line in diff_lines(&old, &history[index].content()) {
+                        match line {
+                            DiffLine::Same(line) => println!("  {line}"),
+                            DiffLine::Added(line) => println!("+ {line}"),
+                            DiffLine::Removed(line) => println!("- {line}"),
+                        }
+                    }
+                }
+                None => {
+                    for revision in history {
+                        println!(
+                            "{}\t{}\t{}",
+                            revision.revision, revision.created_at, revision.title
+                        );
+                    }
+                }
+            }
+        }
+        Command::Restore(command) => {
+            service.restore_revision(command.note_id, command.revision)?;
+        }
+        Command::Delete(command) => {
+            service.delete_note(command.note_id)?;
+        }
+        Command::Trash(_) => {
+            for note in service.get_trash()? {
+                println!(
+                    "{}\t{}\t{}",
+                    note.id.unwrap_or_default(),
+                    note.deleted_at.unwrap_or_default(),
+                    note.title
+                );
+            }
+        }
+        Command::Untrash(command) => {
+            service.restore_note(command.note_id)?;
+        }
+        Command::Purge(command) => match (command.note_id, command.all) {
+            (Some(id), false) => service.purge_note(id)?,
+            (None, true) => println!("{}", service.empty_trash()?),
+            _ => bail!("purge needs either a note id or --all"),
+        },
+        Command::Pin(command) => service.pin_note(command.note_id, true)?,
+        Command::Unpin(command) => service.pin_note(command.note_id, false)?,
+        Command::Tag(command) => service.set_tags(command.note_id, command.tags)?,
+        Command::Export(command) => match command.format {
+            ExportFormat::Markdown => {
+                let count = notes::export_dir(&service, &command.path)?;
+                println!("exported {count} notes to {}", command.path.display());
+            }
+            ExportFormat::Json => {
+                let archive = service.export_archive()?;
+                archive.write(&command.path)?;
+                println!(
+                    "exported {} notes to {}",
+                    archive.notes.len(),
+                    command.path.display()
+                );
+            }
+        },
+        Command::Import(command) => {
+            let format = command.format.unwrap_or(if command.path.is_dir() {
+                ImportFormat::Markdown
+            } else if command.path.extension().is_some_and(|ext| ext == "enex") {
+                ImportFormat::Enex
+            } else {
+                ImportFormat::Json
+            });
+            match format {
+                ImportFormat::Markdown => {
+                    let report = notes::import_dir(&mut service, &command.path)?;
+                    println!("imported {report}");
+                }
+                ImportFormat::Json => {
+                    let archive = Archive::read(&command.path)?;
+                    let report = service.import_archive(&archive, command.on_conflict)?;
+                    println!("imported {report}");
+                }
+                ImportFormat::Obsidian | ImportFormat::Enex | ImportFormat::Keep => {
+                    let report = match format {
+                        ImportFormat::Obsidian => {
+                            let attachments = command
+                                .attachments
+                                .unwrap_or_else(|| config.db.with_file_name("attachments"));
+                            notes::import_obsidian(&mut service, &command.path, &attachments)?
+                        }
+                        ImportFormat::Enex => notes::import_enex(&mut service, &command.path)?,
+                        _ => notes::import_keep(&mut service, &command.path)?,
+                    };
+                    println!("imported {report}");
+                    for problem in &report.problems {
+                        eprintln!("{}", problem.as_str().yellow());
+                    }
+                }
+            }
+        }
+        Command::Sync(command) => {
+            let remote = command.remote.unwrap_or(config.git_remote);
+            println!("{}", service.sync(&remote)?);
+        }
+        Command::Encrypt(_) | Command::Decrypt(_) => unreachable!("handled before opening"),
+    }
     Ok(())
 }
+
+/// the passphrase of the vault, $NOTES_PASSPHRASE or asked without echoing it
+fn passphrase(prompt: &str) -> anyhow::Result<String> {
+    match std::env::var("NOTES_PASSPHRASE") {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => passphrase_prompt(prompt),
+    }
+}
+
+fn passphrase_prompt(prompt: &str) -> anyhow::Result<String> {
+    rpassword::prompt_password(prompt).context("read the passphrase")
+}
+
+fn find_notebook(service: &NoteService, path: Option<&str>) -> anyhow::Result<Option<usize>> {
+    path.map(|path| service.find_notebook(path).map(|notebook| notebook.id))
+        .transpose()
+}
+
+fn print_notebooks(notebooks: &[Notebook], parent: Option<usize>, depth: usize) {
+    for notebook in notebooks.iter().filter(|nb| nb.parent_id == parent) {
+        println!("{}{}\t{}", "  ".repeat(depth), notebook.name, notebook.id);
+        print_notebooks(notebooks, Some(notebook.id), depth + 1);
+    }
+}
@@ -1,18 +1,59 @@
-use std::{error::Error, time::Duration};
+use std::{error::Error, path::PathBuf};
 
 use argh::FromArgs;
 
 /// Demo
 #[derive(Debug, FromArgs)]
 struct Cli {
+    /// config file, $XDG_CONFIG_HOME/notes/config.toml by default.
+    #[argh(option)]
+    config: Option<PathBuf>,
+    /// profile of the config to open, v switches to another one.
+    #[argh(option)]
+    profile: Option<String>,
+    /// sqlite database of the notes, $XDG_DATA_HOME/notes/notes.db by default.
+    #[argh(option)]
+    db: Option<PathBuf>,
     /// time in ms between two ticks.
-    #[argh(option, default = "200")]
-    tick_rate: u64,
+    #[argh(option)]
+    tick_rate: Option<u64>,
+    /// days deleted notes stay in the trash.
+    #[argh(option)]
+    trash_days: Option<u32>,
+    /// keybindings file, $XDG_CONFIG_HOME/notes/keys.toml by default.
+    #[argh(option)]
+    keys: Option<PathBuf>,
+    /// colours, a built-in theme or a toml file: classic, dark, light, high-contrast or ansi16.
+    #[argh(option)]
+    theme: Option<String>,
+    /// command notes are edited with, vim by default.
+    #[argh(option)]
+    editor: Option<String>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli: Cli = argh::from_env();
-    let tick_rate = Duration::from_millis(cli.tick_rate);
-    notes::run(tick_rate)?;
+    let mut config = notes::Config::load(cli.config.as_deref())?;
+    config.override_with(notes::Overrides {
+        db: cli.db,
+        ..notes::Overrides::default()
+    });
+    if let Some(tick_rate) = cli.tick_rate {
+        config.tick_rate = tick_rate;
+    }
+    if let Some(days) = cli.trash_days {
+        config.trash_days = days;
+    }
+    if let Some(keys) = cli.keys {
+        config.keys = keys;
+    }
+    if let Some(theme) = cli.theme {
+        config.theme = theme;
+    }
+    if let Some(editor) = cli.editor {
+        config.editor = editor;
+    }
+    config.select_profile(cli.profile.as_deref())?;
+    notes::run(&config)?;
     Ok(())
 }
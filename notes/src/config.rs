@@ -0,0 +1,375 @@
+// settings of the cli and the tui, loaded once and handed to whatever needs them.
+// every setting has a default, the config file overrides the defaults, environment
+// variables override the file and command line flags override everything:
+//
+//     db = "/home/me/notes.db"
+//     store = "sqlite"
+//     git_remote = "origin"
+//     commit_interval = 60
+//     ai_engine = "http://localhost:8000"
+//     semantic_threshold = 0.4
+//     editor = "nvim"
+//     tick_rate = 100
+//     trash_days = 7
+//     keys = "/home/me/notes-keys.toml"
+//     theme = "dark"
+//
+// profiles keep separate vaults of notes, each with its own database and ai engine.
+// `profile` picks the one to open, the settings outside profiles are the `default` one:
+//
+//     profile = "work"
+//
+//     [profiles.work]
+//     db = "/home/me/work.db"
+//     ai_engine = "http://localhost:8001"
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    keymap::Keymap,
+    service::{DEFAULT_SEMANTIC_THRESHOLD, DEFAULT_TRASH_RETENTION_DAYS},
+    store::StoreKind,
+    theme::Theme,
+};
+
+/// environment variables and the setting each one overrides
+pub const ENV_VARS: [(&str, &str); 12] = [
+    ("NOTES_PROFILE", "profile"),
+    ("NOTES_DB", "db"),
+    ("NOTES_STORE", "store"),
+    ("NOTES_GIT_REMOTE", "git_remote"),
+    ("NOTES_COMMIT_INTERVAL", "commit_interval"),
+    ("AI_ENGINE", "ai_engine"),
+    ("NOTES_SEMANTIC_THRESHOLD", "semantic_threshold"),
+    ("NOTES_EDITOR", "editor"),
+    ("NOTES_TICK_RATE", "tick_rate"),
+    ("NOTES_TRASH_DAYS", "trash_days"),
+    ("NOTES_KEYS", "keys"),
+    ("NOTES_THEME", "theme"),
+];
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// sqlite database of the notes, `notes.db` in the data directory by default
+    pub db: PathBuf,
+    /// what `db` is, a sqlite database or a directory of markdown files
+    pub store: StoreKind,
+    /// remote a git store syncs with
+    pub git_remote: String,
+    /// seconds a git store waits after a write before committing it with the writes after it
+    pub commit_interval: u64,
+    /// url of the ai embedding engine, semantic search needs one
+    pub ai_engine: Option<String>,
+    /// smallest score a note needs to be a semantic search result
+    pub semantic_threshold: f64,
+    /// command editing notes, it gets the file to edit as its last argument
+    pub editor: String,
+    /// time in ms between two ticks of the tui
+    pub tick_rate: u64,
+    /// days deleted notes stay in the trash
+    pub trash_days: u32,
+    /// keybindings file of the tui, the defaults are used when it doesn't exist
+    pub keys: PathBuf,
+    /// a built-in theme, a theme in the themes directory or a theme file
+    pub theme: String,
+    /// profile opened unless another one is asked for, the selected one after `select_profile`
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    /// vault settings given by a variable or a flag, they win over the profile selected first
+    #[serde(skip)]
+    pub overrides: Overrides,
+}
+
+/// `db`, `store` and `ai_engine` set by a variable or a flag instead of the config file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    pub db: Option<PathBuf>,
+    pub store: Option<StoreKind>,
+    pub ai_engine: Option<String>,
+}
+
+/// name of the profile made of the settings outside `profiles`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// a vault of notes
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub db: PathBuf,
+    #[serde(default)]
+    pub store: StoreKind,
+    /// a profile without one has no semantic search
+    pub ai_engine: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            db: data_dir().join("notes.db"),
+            store: StoreKind::Sqlite,
+            git_remote: "origin".to_string(),
+            commit_interval: 60,
+            ai_engine: None,
+            semantic_threshold: DEFAULT_SEMANTIC_THRESHOLD,
+            editor: "vim".to_string(),
+            tick_rate: 200,
+            trash_days: DEFAULT_TRASH_RETENTION_DAYS,
+            keys: config_dir().join("keys.toml"),
+            theme: crate::theme::DEFAULT_THEME.to_string(),
+            profile: None,
+            profiles: BTreeMap::new(),
+            overrides: Overrides::default(),
+        }
+    }
+}
+
+impl Config {
+    /// the config file and the environment over the defaults. the file is `path`, then
+    /// `$NOTES_CONFIG`, then `config.toml` in the config directory when it exists
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os("NOTES_CONFIG").map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Config::read(&path)?,
+            None => {
+                let path = default_config_path();
+                if path.exists() {
+                    Config::read(&path)?
+                } else {
+                    Config::default()
+                }
+            }
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Config> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        Config::parse(&text).with_context(|| format!("config file {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Config> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// overrides the settings that have a variable in `ENV_VARS`
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        let mut overrides = Overrides::default();
+        for (name, _) in ENV_VARS {
+            let Some(value) = var(name).filter(|value| !value.is_empty()) else {
+                continue;
+            };
+            match name {
+                "NOTES_PROFILE" => self.profile = Some(value),
+                "NOTES_DB" => overrides.db = Some(PathBuf::from(value)),
+                "NOTES_STORE" => {
+                    overrides.store = value
+                        .parse()
+                        .map_err(anyhow::Error::msg)
+                        .map(Some)
+                        .with_context(|| format!("{name}={value:?} is not a valid value"))?
+                }
+                "NOTES_GIT_REMOTE" => self.git_remote = value,
+                "NOTES_COMMIT_INTERVAL" => self.commit_interval = parse_var(name, &value)?,
+                "AI_ENGINE" => overrides.ai_engine = Some(value),
+                "NOTES_SEMANTIC_THRESHOLD" => self.semantic_threshold = parse_var(name, &value)?,
+                "NOTES_EDITOR" => self.editor = value,
+                "NOTES_TICK_RATE" => self.tick_rate = parse_var(name, &value)?,
+                "NOTES_TRASH_DAYS" => self.trash_days = parse_var(name, &value)?,
+                "NOTES_KEYS" => self.keys = PathBuf::from(value),
+                "NOTES_THEME" => self.theme = value,
+                _ => unreachable!("{name} is not in ENV_VARS"),
+            }
+        }
+        self.override_with(overrides);
+        Ok(())
+    }
+
+    /// sets `db`, `store` and `ai_engine` so that `select_profile` keeps them, later overrides win
+    pub fn override_with(&mut self, overrides: Overrides) {
+        self.apply(&overrides);
+        let Overrides {
+            db,
+            store,
+            ai_engine,
+        } = overrides;
+        self.overrides.db = db.or(self.overrides.db.take());
+        self.overrides.store = store.or(self.overrides.store.take());
+        self.overrides.ai_engine = ai_engine.or(self.overrides.ai_engine.take());
+    }
+
+    fn apply(&mut self, overrides: &Overrides) {
+        if let Some(db) = &overrides.db {
+            self.db = db.clone();
+        }
+        if let Some(store) = overrides.store {
+            self.store = store;
+        }
+        if let Some(ai_engine) = &overrides.ai_engine {
+            self.ai_engine = Some(ai_engine.clone());
+        }
+    }
+
+    /// points `db`, `store` and `ai_engine` at a profile, `None` selects the `profile` of the config.
+    /// call it after the other settings are final, they become the `default` profile. the
+    /// overrides win over the first profile selected, switching again opens the profile as it is
+    pub fn select_profile(&mut self, name: Option<&str>) -> anyhow::Result<()> {
+        self.profiles
+            .entry(DEFAULT_PROFILE.to_string())
+            .or_insert_with(|| Profile {
+                db: self.db.clone(),
+                store: self.store,
+                ai_engine: self.ai_engine.clone(),
+            });
+        let name = name
+            .or(self.profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+            .to_string();
+        let Some(profile) = self.profiles.get(&name) else {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            bail!("unknown profile {name:?}, one of {}", names.join(", "));
+        };
+        self.db = profile.db.clone();
+        self.store = profile.store;
+        self.ai_engine = profile.ai_engine.clone();
+        self.profile = Some(name);
+        let overrides = std::mem::take(&mut self.overrides);
+        self.apply(&overrides);
+        Ok(())
+    }
+
+    pub fn keymap(&self) -> anyhow::Result<Keymap> {
+        Keymap::load(&self.keys)
+    }
+
+    pub fn theme(&self) -> anyhow::Result<Theme> {
+        Theme::load(&self.theme)
+    }
+}
+
+fn parse_var<T: FromStr>(name: &str, value: &str) -> anyhow::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .parse()
+        .with_context(|| format!("{name}={value:?} is not a valid value"))
+}
+
+/// `$XDG_CONFIG_HOME/notes`, or `~/.config/notes` when it isn't set
+pub fn config_dir() -> PathBuf {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    config.join("notes")
+}
+
+/// `$XDG_DATA_HOME/notes`, or `~/.local/share/notes` when it isn't set
+pub fn data_dir() -> PathBuf {
+    let data = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .unwrap_or_default();
+    data.join("notes")
+}
+
+pub fn default_config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Overrides};
+    use std::path::PathBuf;
+
+    #[test]
+    fn layers() {
+        let mut config =
+            Config::parse("db = \"file.db\"\neditor = \"nano\"\ntick_rate = 50").unwrap();
+        assert_eq!(config.db, PathBuf::from("file.db"));
+        assert_eq!(config.tick_rate, 50);
+        assert_eq!(
+            config.semantic_threshold,
+            Config::default().semantic_threshold
+        );
+
+        config
+            .apply_env(|name| match name {
+                "NOTES_DB" => Some("env.db".to_string()),
+                "AI_ENGINE" => Some("http://localhost:8000".to_string()),
+                "NOTES_TICK_RATE" => Some(String::new()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.db, PathBuf::from("env.db"));
+        assert_eq!(config.ai_engine.as_deref(), Some("http://localhost:8000"));
+        assert_eq!(config.editor, "nano");
+        assert_eq!(config.tick_rate, 50);
+
+        let bad = config.apply_env(|name| (name == "NOTES_TRASH_DAYS").then(|| "a week".into()));
+        assert!(bad.is_err());
+        assert!(Config::parse("database = \"notes.db\"").is_err());
+    }
+
+    #[test]
+    fn profiles() {
+        let text = "db = \"own.db\"\nprofile = \"work\"\n\n[profiles.work]\ndb = \"work.db\"\nai_engine = \"http://localhost:8001\"\n\n[profiles.home]\ndb = \"home.db\"";
+        let mut config = Config::parse(text).unwrap();
+        config.ai_engine = Some("http://localhost:8000".to_string());
+        config.select_profile(None).unwrap();
+        assert_eq!(config.db, PathBuf::from("work.db"));
+        assert_eq!(config.ai_engine.as_deref(), Some("http://localhost:8001"));
+
+        config.select_profile(Some("home")).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("home"));
+        assert_eq!(config.ai_engine, None);
+        config.select_profile(Some("default")).unwrap();
+        assert_eq!(config.db, PathBuf::from("own.db"));
+        assert_eq!(config.ai_engine.as_deref(), Some("http://localhost:8000"));
+        assert_eq!(
+            config.profiles.keys().collect::<Vec<_>>(),
+            ["default", "home", "work"]
+        );
+        assert!(config.select_profile(Some("play")).is_err());
+    }
+
+    #[test]
+    fn overrides_win_over_the_first_profile() {
+        let text = "profile = \"work\"\n\n[profiles.work]\ndb = \"work.db\"\nai_engine = \"http://localhost:8001\"";
+        let mut config = Config::parse(text).unwrap();
+        config
+            .apply_env(|name| (name == "NOTES_DB").then(|| "env.db".to_string()))
+            .unwrap();
+        config.override_with(Overrides {
+            ai_engine: Some("http://localhost:8000".to_string()),
+            ..Overrides::default()
+        });
+        config.select_profile(None).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("work"));
+        assert_eq!(config.db, PathBuf::from("env.db"));
+        assert_eq!(config.ai_engine.as_deref(), Some("http://localhost:8000"));
+
+        config.select_profile(Some("work")).unwrap();
+        assert_eq!(config.db, PathBuf::from("work.db"));
+        assert_eq!(config.ai_engine.as_deref(), Some("http://localhost:8001"));
+        config.select_profile(Some("default")).unwrap();
+        assert_eq!(config.db, PathBuf::from("env.db"));
+    }
+}
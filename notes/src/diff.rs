@@ -0,0 +1,67 @@
+// line based diff between two texts, used to compare revisions of a note
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// walks the longest common subsequence of lines, notes are small enough for the quadratic table
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|&line| DiffLine::Removed(line)));
+    lines.extend(new[j..].iter().map(|&line| DiffLine::Added(line)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, DiffLine::*};
+
+    #[test]
+    fn diff() {
+        let old = "title\na\nb\nc";
+        let new = "title\na\nx\nc\nd";
+        assert_eq!(
+            diff_lines(old, new),
+            [
+                Same("title"),
+                Same("a"),
+                Removed("b"),
+                Added("x"),
+                Same("c"),
+                Added("d")
+            ]
+        );
+        assert_eq!(diff_lines("", "a"), [Added("a")]);
+    }
+}
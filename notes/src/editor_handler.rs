@@ -2,7 +2,12 @@ use std::io::Write;
 use std::{fs, process::Command};
 use tempfile::NamedTempFile;
 
-pub fn edit_with_vim(content: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+/// opens the text in the editor command of the config, like `vim` or `code --wait`,
+/// and returns it once the editor exits. `None` when the editor failed, like vim after `:cq`
+pub fn edit_with(
+    editor: &str,
+    content: Option<&str>,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
     // Create a temporary file.
     let mut file = NamedTempFile::new()?;
     writeln!(file, "{}", content.unwrap_or_default())?;
@@ -10,10 +15,23 @@ pub fn edit_with_vim(content: Option<&str>) -> Result<String, Box<dyn std::error
     // Get the path of the temporary file.
     let file_path = file.path().to_str().unwrap_or_default().to_string();
 
-    // Launch Vim on the file.
-    Command::new("vim").arg(&file_path).status()?;
+    // Launch the editor on the file.
+    let mut args = editor.split_whitespace();
+    let program = args.next().ok_or("no editor configured")?;
+    let status = Command::new(program)
+        .args(args)
+        .arg(&file_path)
+        .status()
+        .map_err(|err| format!("run the editor {program:?}: {err}"))?;
+    if !status.success() {
+        return Ok(None);
+    }
     // Read the contents of the file back into a String.
-    let contents = fs::read_to_string(file_path)?;
+    let mut contents = fs::read_to_string(file_path)?;
+    // the newline written after the content isn't part of it
+    if contents.ends_with('\n') {
+        contents.pop();
+    }
 
-    Ok(contents)
+    Ok(Some(contents))
 }
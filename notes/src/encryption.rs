@@ -0,0 +1,415 @@
+// encryption of a sqlite vault at rest. the key comes from a passphrase through argon2id and
+// the title and text of every note and of every revision are sealed with xchacha20-poly1305,
+// bound to the note, revision and field they belong to. so changing a sealed note in the
+// database, or moving sealed texts around, makes it fail to open instead of showing garbage.
+// tags, notebook names and times stay readable
+//
+// an unlocked vault lives in a memory store: everything is opened once when it unlocks, search
+// and links work on the open notes in memory, and every write seals the notes and revisions it
+// changed and writes them in one transaction. only one app should have an encrypted vault open
+// at a time, they don't see the writes of each other
+//
+// the key of the vault is stored as `v1$<salt>$<check>`, the check is a sealed known text that
+// tells a wrong passphrase apart
+
+use anyhow::{anyhow, bail, Context};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+
+use crate::{
+    archive::{Archive, ArchiveNote, ArchiveNotebook, ArchiveRevision, ARCHIVE_VERSION},
+    memory_store::{Changes, Contents, MemoryStore, OldRevisions, Persist},
+    pool::RepositoryPool,
+    repository::{Note, Revision},
+    store::NoteStore,
+};
+
+/// text sealed into the key of the vault, opening it proves the passphrase
+const CHECK: &str = "notes vault";
+
+const SALT_LEN: usize = 16;
+
+/// nonce of xchacha20, random for every sealed text
+const NONCE_LEN: usize = 24;
+
+pub type EncryptedStore = MemoryStore<SealedDb>;
+
+/// the key of an unlocked vault
+pub struct VaultKey {
+    cipher: XChaCha20Poly1305,
+    /// how the key is stored in the database
+    record: String,
+}
+
+impl VaultKey {
+    /// a key with a new salt
+    fn create(passphrase: &str) -> anyhow::Result<VaultKey> {
+        if passphrase.is_empty() {
+            bail!("the passphrase is empty");
+        }
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = derive(passphrase, &salt)?;
+        let check = seal(&cipher, CHECK, "check")?;
+        Ok(VaultKey {
+            cipher,
+            record: format!("v1${}${check}", STANDARD.encode(salt)),
+        })
+    }
+
+    /// the key stored as `record`, if the passphrase is the one it was made with
+    fn unlock(record: &str, passphrase: &str) -> anyhow::Result<VaultKey> {
+        let mut parts = record.split('$');
+        let (Some("v1"), Some(salt), Some(check), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("unknown vault key {record:?}");
+        };
+        let salt = STANDARD.decode(salt).context("salt of the vault key")?;
+        let cipher = derive(passphrase, &salt)?;
+        if open(&cipher, check, "check").ok().as_deref() != Some(CHECK) {
+            bail!("wrong passphrase");
+        }
+        Ok(VaultKey {
+            cipher,
+            record: record.to_string(),
+        })
+    }
+
+    fn seal(&self, text: &str, place: &str) -> anyhow::Result<String> {
+        seal(&self.cipher, text, place)
+    }
+
+    fn open(&self, sealed: &str, place: &str) -> anyhow::Result<String> {
+        open(&self.cipher, sealed, place)
+    }
+}
+
+/// argon2id with the parameters owasp recommends, 19 MiB of memory and 2 passes
+fn derive(passphrase: &str, salt: &[u8]) -> anyhow::Result<XChaCha20Poly1305> {
+    let params = Params::new(19_456, 2, 1, Some(32)).map_err(|err| anyhow!("{err}"))?;
+    let mut key = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("derive the vault key: {err}"))?;
+    XChaCha20Poly1305::new_from_slice(&key).map_err(|err| anyhow!("vault key: {err}"))
+}
+
+/// where a sealed text belongs, the revision of the note itself is 0. it is sealed along with
+/// the text, which doesn't open anywhere else
+fn place(note_id: usize, revision: usize, field: &str) -> String {
+    format!("note {note_id} revision {revision} {field}")
+}
+
+/// the nonce and the text sealed at `place` in base64
+fn seal(cipher: &XChaCha20Poly1305, text: &str, place: &str) -> anyhow::Result<String> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: text.as_bytes(),
+        aad: place.as_bytes(),
+    };
+    let sealed = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| anyhow!("seal a note"))?;
+    Ok(STANDARD.encode([nonce.as_slice(), &sealed].concat()))
+}
+
+fn open(cipher: &XChaCha20Poly1305, sealed: &str, place: &str) -> anyhow::Result<String> {
+    let bytes = STANDARD
+        .decode(sealed)
+        .context("sealed note isn't base64")?;
+    if bytes.len() < NONCE_LEN {
+        bail!("sealed note is too short");
+    }
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: sealed,
+        aad: place.as_bytes(),
+    };
+    let text = cipher
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("a note was changed outside the app or the key is wrong"))?;
+    String::from_utf8(text).context("opened note isn't utf-8")
+}
+
+/// writes the changes of an unlocked vault sealed to its database
+pub struct SealedDb {
+    pool: RepositoryPool,
+    key: VaultKey,
+}
+
+impl Persist for SealedDb {
+    fn save(&self, contents: &Contents, changes: &Changes) -> anyhow::Result<()> {
+        let seal = |text: &str, place: &str| self.key.seal(text, place);
+        let mut replaced = Vec::new();
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for (&id, old) in &changes.notes {
+            let Some(note) = contents.notes.get(&id) else {
+                if old.is_some() {
+                    removed.push(id);
+                }
+                continue;
+            };
+            let revisions = contents.revisions.get(&id).map_or(&[][..], Vec::as_slice);
+            // only the revisions the write added are sealed, unless it replaced the history
+            match changes.revisions.get(&id) {
+                Some(OldRevisions::Replaced(_)) => {
+                    replaced.push(archive_note(note, revisions, seal)?);
+                }
+                Some(&OldRevisions::Appended(len)) => {
+                    changed.push(archive_note(note, &revisions[len..], seal)?);
+                }
+                None if old.as_ref() != Some(note) => {
+                    changed.push(archive_note(note, &[], seal)?);
+                }
+                None => (),
+            }
+        }
+        if replaced.is_empty()
+            && changed.is_empty()
+            && removed.is_empty()
+            && changes.notebooks.is_empty()
+        {
+            return Ok(());
+        }
+        self.pool
+            .get()?
+            .write_sealed(&notebooks_of(contents), &replaced, &changed, &removed)
+            .context("write sealed notes")
+    }
+}
+
+impl EncryptedStore {
+    /// opens every note of the encrypted vault in memory
+    pub fn unlock(pool: RepositoryPool, passphrase: &str) -> anyhow::Result<EncryptedStore> {
+        let (contents, key) = {
+            let db = pool.get()?;
+            let record = db.get_vault_key()?.context("the vault isn't encrypted")?;
+            let key = VaultKey::unlock(&record, passphrase)?;
+            (
+                read_contents(&pool, |text, place| key.open(text, place))?,
+                key,
+            )
+        };
+        Ok(MemoryStore::with_contents(contents, SealedDb { pool, key }))
+    }
+}
+
+/// whether the database is encrypted and needs a passphrase to open
+pub fn is_encrypted(pool: &RepositoryPool) -> anyhow::Result<bool> {
+    Ok(pool.get()?.get_vault_key()?.is_some())
+}
+
+/// seals every note of the vault with a key made from the passphrase, returns how many
+pub fn encrypt_vault(pool: &RepositoryPool, passphrase: &str) -> anyhow::Result<usize> {
+    if is_encrypted(pool)? {
+        bail!("the vault is already encrypted");
+    }
+    let key = VaultKey::create(passphrase)?;
+    let contents = read_contents(pool, |text, _| Ok(text.to_string()))?;
+    let archive = archive_of(&contents, |text, place| key.seal(text, place))?;
+    pool.get()?
+        .replace_notes(&archive, Some(&key.record))
+        .context("write sealed notes")?;
+    Ok(archive.notes.len())
+}
+
+/// opens every note of the vault for good, returns how many
+pub fn decrypt_vault(pool: &RepositoryPool, passphrase: &str) -> anyhow::Result<usize> {
+    let record = pool
+        .get()?
+        .get_vault_key()?
+        .context("the vault isn't encrypted")?;
+    let key = VaultKey::unlock(&record, passphrase)?;
+    let contents = read_contents(pool, |text, place| key.open(text, place))?;
+    let archive = archive_of(&contents, |text, _| Ok(text.to_string()))?;
+    pool.get()?
+        .replace_notes(&archive, None)
+        .context("write opened notes")?;
+    Ok(archive.notes.len())
+}
+
+/// everything in the database, titles and texts through `open`
+fn read_contents(
+    pool: &RepositoryPool,
+    open: impl Fn(&str, &str) -> anyhow::Result<String>,
+) -> anyhow::Result<Contents> {
+    let mut contents = Contents::default();
+    for notebook in pool.get_notebooks()? {
+        contents.last_notebook_id = contents.last_notebook_id.max(notebook.id);
+        contents.notebooks.insert(notebook.id, notebook);
+    }
+    for note in pool.get_notes()?.into_iter().chain(pool.get_trash()?) {
+        let id = note.id.context("stored note without id")?;
+        let revisions = pool
+            .get_revisions(id)?
+            .into_iter()
+            .map(|revision| {
+                let number = revision.revision;
+                Ok(Revision {
+                    title: open(&revision.title, &place(id, number, "title"))?,
+                    text: open(&revision.text, &place(id, number, "text"))?,
+                    ..revision
+                })
+            })
+            .collect::<anyhow::Result<_>>()
+            .with_context(|| format!("open the history of note {id}"))?;
+        let note = Note {
+            title: open(&note.title, &place(id, 0, "title"))
+                .with_context(|| format!("open note {id}"))?,
+            text: open(&note.text, &place(id, 0, "text"))
+                .with_context(|| format!("open note {id}"))?,
+            ..note
+        };
+        contents.last_note_id = contents.last_note_id.max(id);
+        contents.notes.insert(id, note);
+        contents.revisions.insert(id, revisions);
+    }
+    Ok(contents)
+}
+
+/// every note with its history and every notebook, titles and texts through `seal` with
+/// their place
+fn archive_of(
+    contents: &Contents,
+    seal: impl Fn(&str, &str) -> anyhow::Result<String>,
+) -> anyhow::Result<Archive> {
+    let notes = contents
+        .notes
+        .iter()
+        .map(|(id, note)| {
+            let revisions = contents.revisions.get(id).map_or(&[][..], Vec::as_slice);
+            archive_note(note, revisions, &seal)
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Archive {
+        version: ARCHIVE_VERSION,
+        ai_engine: None,
+        notebooks: notebooks_of(contents),
+        notes,
+    })
+}
+
+fn notebooks_of(contents: &Contents) -> Vec<ArchiveNotebook> {
+    contents
+        .notebooks
+        .values()
+        .map(|notebook| ArchiveNotebook {
+            id: notebook.id,
+            name: notebook.name.clone(),
+            parent_id: notebook.parent_id,
+        })
+        .collect()
+}
+
+/// the note with the revisions, titles and texts through `seal` with their place
+fn archive_note(
+    note: &Note,
+    revisions: &[Revision],
+    seal: impl Fn(&str, &str) -> anyhow::Result<String>,
+) -> anyhow::Result<ArchiveNote> {
+    let id = note.id.context("note without id")?;
+    let revisions = revisions
+        .iter()
+        .map(|revision| {
+            Ok(ArchiveRevision {
+                revision: revision.revision,
+                title: seal(&revision.title, &place(id, revision.revision, "title"))?,
+                text: seal(&revision.text, &place(id, revision.revision, "text"))?,
+                created_at: revision.created_at.clone(),
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(ArchiveNote {
+        id,
+        title: seal(&note.title, &place(id, 0, "title"))?,
+        text: seal(&note.text, &place(id, 0, "text"))?,
+        notebook_id: note.notebook_id,
+        pinned: note.pinned,
+        tags: note.tags.clone(),
+        created_at: note.created_at.clone(),
+        updated_at: note.updated_at.clone(),
+        deleted_at: note.deleted_at.clone(),
+        links: Vec::new(),
+        revisions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt_vault, encrypt_vault, place, EncryptedStore, VaultKey};
+    use crate::{pool::RepositoryPool, repository::Note, store::NoteStore};
+
+    #[test]
+    fn sealed_at_rest() {
+        let pool = RepositoryPool::in_memory().unwrap();
+        let mut note = Note::new(None, "Secret".to_string(), "[[Other]]".to_string());
+        note.tags = vec!["work".to_string()];
+        pool.add(&[note, Note::new(None, "Other".to_string(), String::new())])
+            .unwrap();
+        assert_eq!(encrypt_vault(&pool, "hunter2").unwrap(), 2);
+        assert!(encrypt_vault(&pool, "again").is_err());
+        let stored = pool.get_note(1).unwrap();
+        assert!(!stored.title.contains("Secret"));
+        assert_eq!(stored.tags, ["work"]);
+
+        assert!(EncryptedStore::unlock(pool.clone(), "wrong").is_err());
+        let store = EncryptedStore::unlock(pool.clone(), "hunter2").unwrap();
+        assert_eq!(store.get_note(1).unwrap().title, "Secret");
+        assert_eq!(store.get_links(1).unwrap()[0].target_id, Some(2));
+        // an edit seals its new revision and leaves the older ones as they are
+        let first = pool.get_revision(2, 1).unwrap();
+        let mut note = store.get_note(2).unwrap();
+        note.text = "changed".to_string();
+        store.update(2, note).unwrap();
+        assert_eq!(pool.get_revision(2, 1).unwrap(), first);
+        store.delete(1).unwrap();
+        store.purge(1).unwrap();
+        let id = store
+            .insert(&Note::new(None, "New".to_string(), String::new()))
+            .unwrap();
+        drop(store);
+        assert!(!pool.get_revision(2, 2).unwrap().text.contains("changed"));
+        let store = EncryptedStore::unlock(pool.clone(), "hunter2").unwrap();
+        assert!(store.get_note(1).is_err());
+        assert_eq!(store.get_revisions(2).unwrap().len(), 2);
+        assert_eq!(store.get_revision(id, 1).unwrap().title, "New");
+        drop(store);
+
+        assert_eq!(decrypt_vault(&pool, "hunter2").unwrap(), 2);
+        assert_eq!(pool.get_note(2).unwrap().text, "changed");
+        assert!(decrypt_vault(&pool, "hunter2").is_err());
+
+        // a sealed note changed in the database doesn't open
+        encrypt_vault(&pool, "hunter2").unwrap();
+        let mut tampered = pool.get_note(2).unwrap();
+        tampered.text.replace_range(40..44, "AAAA");
+        pool.update(2, tampered).unwrap();
+        assert!(EncryptedStore::unlock(pool.clone(), "hunter2").is_err());
+
+        // a sealed text only opens in its own place
+        let key = VaultKey::create("hunter2").unwrap();
+        let sealed = key.seal("Secret", &place(1, 0, "title")).unwrap();
+        assert_eq!(key.open(&sealed, &place(1, 0, "title")).unwrap(), "Secret");
+        for other in [
+            place(1, 0, "text"),
+            place(2, 0, "title"),
+            place(1, 1, "title"),
+        ] {
+            assert!(key.open(&sealed, &other).is_err());
+        }
+        let pool = RepositoryPool::in_memory().unwrap();
+        pool.add(&[Note::new(None, "a".to_string(), "b".to_string())])
+            .unwrap();
+        encrypt_vault(&pool, "hunter2").unwrap();
+        let mut swapped = pool.get_note(1).unwrap();
+        (swapped.title, swapped.text) = (swapped.text, swapped.title);
+        pool.update(1, swapped).unwrap();
+        assert!(EncryptedStore::unlock(pool, "hunter2").is_err());
+    }
+}
@@ -0,0 +1,549 @@
+// import of evernote exports. an `.enex` file holds the notes of one notebook, their text is
+// ENML, a kind of xhtml that is turned into markdown here:
+//
+//     <div><b>Plan</b></div><ul><li><en-todo checked="true"/>ship</li></ul>
+//
+// becomes
+//
+//     **Plan**
+//     - [x] ship
+//
+// tags and the created and updated dates are kept. attachments and encrypted text aren't,
+// the report says which notes had them
+
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+use crate::{
+    archive::ConversionReport, markdown_dir::ensure_notebook, repository::Note,
+    service::NoteService,
+};
+
+/// a note of the export before its ENML is converted
+#[derive(Debug, Default)]
+struct EnexNote {
+    title: String,
+    content: String,
+    created: String,
+    updated: String,
+    tags: Vec<String>,
+    resources: usize,
+}
+
+/// imports an `.enex` file, or every one in a directory, into a notebook named after the file
+pub fn import_enex(service: &mut NoteService, path: &Path) -> anyhow::Result<ConversionReport> {
+    let files = if path.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(path)
+            .with_context(|| format!("read {}", path.display()))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "enex"))
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+    if files.is_empty() {
+        bail!("no .enex files in {}", path.display());
+    }
+    let mut report = ConversionReport::default();
+    for file in files {
+        let notebook = file.file_stem().unwrap_or_default().to_string_lossy();
+        let notebook_id = ensure_notebook(service, Path::new(notebook.as_ref()))?;
+        let mut notes = Vec::new();
+        for enex in read_enex(&file).with_context(|| format!("read {}", file.display()))? {
+            let mut problems = Vec::new();
+            let mut note = to_note(&enex, &mut problems);
+            note.notebook_id = notebook_id;
+            let title = &note.title;
+            report.problems.extend(
+                problems
+                    .into_iter()
+                    .map(|problem| format!("{title}: {problem}")),
+            );
+            notes.push(note);
+        }
+        service.import_notes(&notes)?;
+        report.notes += notes.len();
+    }
+    Ok(report)
+}
+
+/// the notes of an export with their ENML as it is
+fn read_enex(path: &Path) -> anyhow::Result<Vec<EnexNote>> {
+    let mut reader = Reader::from_reader(BufReader::new(File::open(path)?));
+    let mut buf = Vec::new();
+    let mut elements: Vec<Vec<u8>> = Vec::new();
+    let mut notes = Vec::new();
+    let mut note: Option<EnexNote> = None;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(start) => {
+                let name = start.name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"note" => note = Some(EnexNote::default()),
+                    b"resource" => note.iter_mut().for_each(|note| note.resources += 1),
+                    _ => {}
+                }
+                elements.push(name);
+            }
+            Event::End(_) => {
+                let name = elements.pop();
+                if name.as_deref() == Some(b"note") {
+                    notes.extend(note.take());
+                }
+            }
+            Event::Text(text) => field(&elements, note.as_mut(), &text.unescape()?),
+            Event::CData(data) => {
+                let text = String::from_utf8_lossy(&data);
+                field(&elements, note.as_mut(), &text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(notes)
+}
+
+/// text inside one of the fields of a note, the fields of its resources are left alone
+fn field(elements: &[Vec<u8>], note: Option<&mut EnexNote>, text: &str) {
+    let (Some(note), [.., parent, name]) = (note, elements) else {
+        return;
+    };
+    if parent != b"note" {
+        return;
+    }
+    match name.as_slice() {
+        b"title" => note.title.push_str(text),
+        b"content" => note.content.push_str(text),
+        b"created" => note.created.push_str(text),
+        b"updated" => note.updated.push_str(text),
+        b"tag" => note.tags.push(text.to_string()),
+        _ => {}
+    }
+}
+
+fn to_note(enex: &EnexNote, problems: &mut Vec<String>) -> Note {
+    let title = match enex.title.trim() {
+        "" => "Untitled",
+        title => title,
+    };
+    let text = match enml_to_markdown(&enex.content) {
+        Ok((text, dropped)) => {
+            problems.extend(dropped);
+            text
+        }
+        Err(err) => {
+            problems.push(format!(
+                "content isn't valid ENML ({err}), kept it as it was"
+            ));
+            enex.content.clone()
+        }
+    };
+    if enex.resources > 0 {
+        problems.push(format!("{} attachments were dropped", enex.resources));
+    }
+    let mut note = Note::new(None, title.to_string(), text);
+    note.tags = enex.tags.clone();
+    note.created_at = enex_time(&enex.created, problems);
+    note.updated_at = enex_time(&enex.updated, problems);
+    note
+}
+
+/// `20240502T093000Z` as `2024-05-02 09:30:00`, the way the database keeps times
+fn enex_time(time: &str, problems: &mut Vec<String>) -> Option<String> {
+    let time = time.trim();
+    if time.is_empty() {
+        return None;
+    }
+    let digits = time.strip_suffix('Z').unwrap_or(time).replace('T', "");
+    if digits.len() != 14 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        problems.push(format!("date {time:?} isn't understood"));
+        return None;
+    }
+    let part = |range: std::ops::Range<usize>| &digits[range];
+    Some(format!(
+        "{}-{}-{} {}:{}:{}",
+        part(0..4),
+        part(4..6),
+        part(6..8),
+        part(8..10),
+        part(10..12),
+        part(12..14)
+    ))
+}
+
+/// html entities ENML takes from xhtml, xml only knows five
+fn entity(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => " ",
+        "ndash" => "–",
+        "mdash" => "—",
+        "hellip" => "…",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "bull" => "•",
+        "copy" => "©",
+        "reg" => "®",
+        "trade" => "™",
+        "euro" => "€",
+        _ => return None,
+    })
+}
+
+/// the markdown of an ENML document, with what it had to leave out
+fn enml_to_markdown(enml: &str) -> anyhow::Result<(String, Vec<String>)> {
+    let mut reader = Reader::from_str(enml);
+    let mut out = Markdown::default();
+    // how to close each open element
+    let mut open: Vec<(Close, Scope)> = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let close = out.open(&start)?;
+                open.push(close);
+            }
+            Event::Empty(start) => {
+                let (close, scope) = out.open(&start)?;
+                out.close(close, scope);
+            }
+            Event::End(_) => {
+                if let Some((close, scope)) = open.pop() {
+                    out.close(close, scope);
+                }
+            }
+            Event::Text(text) => out.text(&text.unescape_with(entity)?),
+            Event::CData(data) => out.text(&String::from_utf8_lossy(&data)),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((out.finish(), out.problems))
+}
+
+/// what ends an element in the markdown
+enum Close {
+    Text(&'static str),
+    Link(String),
+    Line,
+    BlankLine,
+    Fence,
+}
+
+/// what an element changes until it ends
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    None,
+    /// text keeps its whitespace
+    Code,
+    List,
+    /// its text isn't written
+    Hidden,
+}
+
+#[derive(Default)]
+struct Markdown {
+    text: String,
+    /// the number of the next item of each open list, `None` for bullets
+    lists: Vec<Option<usize>>,
+    code: usize,
+    hidden: usize,
+    problems: Vec<String>,
+}
+
+impl Markdown {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    fn current_line(&self) -> &str {
+        self.text.rsplit('\n').next().unwrap_or_default()
+    }
+
+    /// nothing but a list marker on the line yet, a block in a list item stays on it
+    fn after_marker(&self) -> bool {
+        let line = self.current_line().trim();
+        line == "-"
+            || line
+                .strip_suffix('.')
+                .is_some_and(|number| number.bytes().all(|byte| byte.is_ascii_digit()))
+    }
+
+    fn line_break(&mut self) {
+        if !self.current_line().is_empty() && !self.after_marker() {
+            self.text.push('\n');
+        }
+    }
+
+    fn blank_line(&mut self) {
+        self.line_break();
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") && !self.after_marker() {
+            self.text.push('\n');
+        }
+    }
+
+    /// writes the start of the element and returns how it ends
+    fn open(&mut self, start: &BytesStart) -> anyhow::Result<(Close, Scope)> {
+        let attribute = |name: &str| -> anyhow::Result<Option<String>> {
+            Ok(match start.try_get_attribute(name)? {
+                Some(value) => Some(value.unescape_value_with(entity)?.into_owned()),
+                None => None,
+            })
+        };
+        let name = start.name();
+        Ok(match name.as_ref() {
+            b"div" | b"p" | b"blockquote" | b"table" => {
+                // evernote keeps code blocks in a div with a style of its own
+                let code = attribute("style")?.is_some_and(|style| style.contains("-en-codeblock"));
+                if code {
+                    self.open_code()
+                } else if name.as_ref() == b"p" {
+                    self.blank_line();
+                    (Close::BlankLine, Scope::None)
+                } else {
+                    self.line_break();
+                    (Close::Line, Scope::None)
+                }
+            }
+            b"pre" => self.open_code(),
+            b"br" => {
+                self.push("\n");
+                (Close::Text(""), Scope::None)
+            }
+            b"hr" => {
+                self.blank_line();
+                self.push("---\n");
+                (Close::Text(""), Scope::None)
+            }
+            b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => {
+                let level = (name.as_ref()[1] - b'0') as usize;
+                self.blank_line();
+                self.push(&format!("{} ", "#".repeat(level)));
+                (Close::BlankLine, Scope::None)
+            }
+            b"b" | b"strong" => self.wrap("**"),
+            b"i" | b"em" => self.wrap("*"),
+            b"s" | b"strike" | b"del" => self.wrap("~~"),
+            b"code" if self.code == 0 => self.wrap("`"),
+            b"ul" | b"ol" => {
+                self.line_break();
+                self.lists.push((name.as_ref() == b"ol").then_some(1));
+                (Close::Line, Scope::List)
+            }
+            b"li" => {
+                self.line_break();
+                let depth = self.lists.len().saturating_sub(1);
+                self.push(&"  ".repeat(depth));
+                match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        let marker = format!("{number}. ");
+                        *number += 1;
+                        self.push(&marker);
+                    }
+                    _ => self.push("- "),
+                }
+                (Close::Line, Scope::None)
+            }
+            b"tr" => {
+                self.line_break();
+                (Close::Line, Scope::None)
+            }
+            b"td" | b"th" => (Close::Text(" "), Scope::None),
+            b"a" => {
+                let href = attribute("href")?.unwrap_or_default();
+                self.push("[");
+                (Close::Link(href), Scope::None)
+            }
+            b"img" => {
+                let src = attribute("src")?.unwrap_or_default();
+                let alt = attribute("alt")?.unwrap_or_default();
+                self.push(&format!("![{alt}]({src})"));
+                (Close::Text(""), Scope::None)
+            }
+            b"en-todo" => {
+                let checked = attribute("checked")?.is_some_and(|checked| checked == "true");
+                if self.current_line().trim().is_empty() {
+                    self.push("- ");
+                }
+                self.push(if checked { "[x] " } else { "[ ] " });
+                (Close::Text(""), Scope::None)
+            }
+            b"en-crypt" => {
+                self.problems.push("encrypted text was dropped".to_string());
+                self.hidden += 1;
+                (Close::Text(""), Scope::Hidden)
+            }
+            _ => (Close::Text(""), Scope::None),
+        })
+    }
+
+    fn open_code(&mut self) -> (Close, Scope) {
+        self.code += 1;
+        self.line_break();
+        self.push("```\n");
+        (Close::Fence, Scope::Code)
+    }
+
+    fn wrap(&mut self, mark: &'static str) -> (Close, Scope) {
+        self.push(mark);
+        (Close::Text(mark), Scope::None)
+    }
+
+    fn close(&mut self, close: Close, scope: Scope) {
+        match close {
+            Close::Text(text) => self.push(text),
+            Close::Link(href) => {
+                // a link without text shows its address
+                if self.text.ends_with('[') {
+                    self.push(&href);
+                }
+                self.push(&format!("]({href})"));
+            }
+            Close::Line => self.line_break(),
+            Close::BlankLine => self.blank_line(),
+            Close::Fence => {
+                self.line_break();
+                self.push("```\n");
+            }
+        }
+        match scope {
+            Scope::None => {}
+            Scope::Code => self.code -= 1,
+            Scope::List => {
+                self.lists.pop();
+            }
+            Scope::Hidden => self.hidden -= 1,
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.hidden > 0 {
+            return;
+        }
+        if self.code > 0 {
+            self.push(text);
+            return;
+        }
+        // outside of code any run of whitespace is one space, like in html
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let line_start = self.current_line().is_empty() || self.text.ends_with(' ');
+        if text.starts_with(char::is_whitespace) && !line_start {
+            self.push(" ");
+        }
+        self.push(&words.join(" "));
+        if text.ends_with(char::is_whitespace) && !words.is_empty() {
+            self.push(" ");
+        }
+    }
+
+    fn finish(&self) -> String {
+        let mut lines: Vec<&str> = Vec::new();
+        for line in self.text.lines().map(str::trim_end) {
+            // no more than one blank line in a row
+            if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+                continue;
+            }
+            lines.push(line);
+        }
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{enml_to_markdown, import_enex};
+    use crate::{
+        pool::RepositoryPool,
+        service::{NoteService, SortOrder},
+    };
+    use std::fs;
+
+    #[test]
+    fn enml() {
+        let enml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-note SYSTEM "http://xml.evernote.com/pub/enml2.dtd">
+<en-note>
+  <h2>Plan</h2>
+  <div>Ship <b>soon</b>&nbsp;and <a href="https://example.com">tell</a> people.</div>
+  <div><br/></div>
+  <ul><li><div>first</div></li><li><div>second</div><ol><li>inner</li></ol></li></ul>
+  <div><en-todo checked="true"/>done</div>
+  <div><en-todo/>open</div>
+  <div style="-en-codeblock:true"><div>let x = 1;</div></div>
+  <en-crypt cipher="AES">c2VjcmV0</en-crypt>
+  <en-media type="image/png" hash="abc"/>
+</en-note>"#;
+        let (text, problems) = enml_to_markdown(enml).unwrap();
+        assert_eq!(
+            text,
+            "## Plan\n\nShip **soon** and [tell](https://example.com) people.\n\n\
+             - first\n- second\n  1. inner\n- [x] done\n- [ ] open\n```\nlet x = 1;\n```"
+        );
+        assert_eq!(problems, ["encrypted text was dropped"]);
+    }
+
+    #[test]
+    fn import() {
+        let dir = tempfile::tempdir().unwrap();
+        let enex = r#"<?xml version="1.0" encoding="UTF-8"?>
+<en-export export-date="20240601T120000Z" application="Evernote">
+  <note>
+    <title>Groceries &amp; more</title>
+    <content><![CDATA[<en-note><div>milk</div></en-note>]]></content>
+    <created>20240502T093000Z</created>
+    <updated>20240503T170211Z</updated>
+    <tag>home</tag>
+    <tag>Shopping List</tag>
+    <resource><data encoding="base64">aGk=</data><mime>image/png</mime></resource>
+  </note>
+  <note>
+    <title></title>
+    <content><![CDATA[<en-note>broken<div></en-note>]]></content>
+  </note>
+</en-export>"#;
+        fs::write(dir.path().join("Personal.enex"), enex).unwrap();
+
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let report = import_enex(&mut service, dir.path()).unwrap();
+        assert_eq!(report.notes, 2);
+        assert_eq!(report.problems.len(), 2);
+        assert_eq!(
+            report.problems[0],
+            "Groceries & more: 1 attachments were dropped"
+        );
+        assert!(report.problems[1].starts_with("Untitled: content isn't valid ENML"));
+
+        let notebook = service.find_notebook("Personal").unwrap();
+        let notes = service
+            .get_notes_in(Some(notebook.id), SortOrder::Title)
+            .unwrap();
+        let note = &notes[0];
+        assert_eq!(note.title, "Groceries & more");
+        assert_eq!(note.text, "milk");
+        assert_eq!(note.tags, ["Shopping-List", "home"]);
+        assert_eq!(note.created_at.as_deref(), Some("2024-05-02 09:30:00"));
+        assert_eq!(note.updated_at.as_deref(), Some("2024-05-03 17:02:11"));
+    }
+}
@@ -0,0 +1,323 @@
+// a vault kept as a directory of markdown files, for teams who track their notes with git.
+// the files look like the ones `export_dir` writes: folders are notebooks and every note is
+// a file with yaml front matter holding its id, title, tags and times. notes in the trash
+// move to the same folders under `.trash`, with the time they were deleted
+//
+//     notes/
+//         work/
+//             Meeting Notes.md
+//         .trash/
+//             work/
+//                 Old Plan.md
+//
+// the directory is read once when the store opens, every write after that rewrites the files
+// of the notes it changed. history is only kept while the store is open, the files hold the
+// current version of each note. files dropped in by hand become notes, the ones without an
+// id get one written into their front matter
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+
+use crate::{
+    markdown_dir::{file_name, from_markdown, notebook_dir, to_markdown},
+    memory_store::{now, Changes, Contents, MemoryStore, Persist},
+    repository::{Note, Notebook, Revision},
+};
+
+/// folder of the trash inside the vault
+const TRASH: &str = ".trash";
+
+pub type FileStore = MemoryStore<MarkdownFiles>;
+
+/// writes the notes of a memory store to the files of a directory
+#[derive(Debug)]
+pub struct MarkdownFiles {
+    dir: PathBuf,
+    /// the file of every note
+    paths: Mutex<HashMap<usize, PathBuf>>,
+}
+
+impl FileStore {
+    /// reads the vault at `dir`, creating the directory when it doesn't exist
+    pub fn open(dir: impl AsRef<Path>) -> anyhow::Result<FileStore> {
+        let (contents, files) = MarkdownFiles::read(dir.as_ref())?;
+        Ok(MemoryStore::with_contents(contents, files))
+    }
+}
+
+impl MarkdownFiles {
+    /// the notes of the vault at `dir` and the files they are in
+    pub(crate) fn read(dir: &Path) -> anyhow::Result<(Contents, MarkdownFiles)> {
+        fs::create_dir_all(dir).with_context(|| format!("create vault {}", dir.display()))?;
+        let mut contents = Contents::default();
+        let mut read = Vec::new();
+        let (folders, files) = walk(dir)?;
+        for folder in folders {
+            notebook_of(&mut contents, &folder, true)?;
+        }
+        for (relative, trashed) in files {
+            let path = dir.join(&relative);
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("read note {}", path.display()))?;
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let (front, body) = from_markdown(&text, &stem)
+                .with_context(|| format!("front matter of {}", path.display()))?;
+            let mut folder = relative.parent().unwrap_or(Path::new(""));
+            if trashed {
+                folder = folder.strip_prefix(TRASH).unwrap_or(folder);
+            }
+            // notes in the trash don't bring back the notebooks they were in
+            let notebook_id = notebook_of(&mut contents, folder, !trashed)?;
+            read.push((path, front, body.to_string(), notebook_id, trashed));
+        }
+
+        // files copied from another one share its id, the first one keeps it
+        let mut paths = HashMap::new();
+        let mut unnumbered = Vec::new();
+        for (path, front, body, notebook_id, trashed) in read {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let read_at = now();
+            let mut note = Note::new(None, front.title.unwrap_or_else(|| stem.to_string()), body);
+            note.notebook_id = notebook_id;
+            note.tags = front.tags;
+            note.pinned = front.pinned;
+            note.created_at = Some(front.created.unwrap_or_else(|| read_at.clone()));
+            note.updated_at = front.updated.or(note.created_at.clone());
+            if trashed {
+                note.deleted_at = Some(front.deleted.unwrap_or(read_at));
+            }
+            match front.id.filter(|id| !contents.notes.contains_key(id)) {
+                Some(id) => {
+                    note.id = Some(id);
+                    contents.notes.insert(id, note);
+                    paths.insert(id, path);
+                }
+                None => unnumbered.push((path, note)),
+            }
+        }
+        contents.last_note_id = contents.notes.keys().max().copied().unwrap_or_default();
+        let mut renumbered = Vec::new();
+        for (path, mut note) in unnumbered {
+            contents.last_note_id += 1;
+            let id = contents.last_note_id;
+            note.id = Some(id);
+            contents.notes.insert(id, note);
+            paths.insert(id, path);
+            renumbered.push(id);
+        }
+        for note in contents.notes.values_mut() {
+            note.tags.sort();
+            note.tags.dedup();
+        }
+        // the history starts with the files as they are
+        for (&id, note) in &contents.notes {
+            let revision = Revision {
+                note_id: id,
+                revision: 1,
+                title: note.title.clone(),
+                text: note.text.clone(),
+                created_at: note.updated_at.clone().unwrap_or_default(),
+            };
+            contents.revisions.insert(id, vec![revision]);
+        }
+
+        let files = MarkdownFiles {
+            dir: dir.to_path_buf(),
+            paths: Mutex::new(paths),
+        };
+        for id in renumbered {
+            files.write_note(&contents, &contents.notes[&id])?;
+        }
+        Ok((contents, files))
+    }
+
+    /// reads the notes of the directory again, after something else changed its files
+    pub(crate) fn reload(&self) -> anyhow::Result<Contents> {
+        let (contents, files) = MarkdownFiles::read(&self.dir)?;
+        *self.paths.lock().unwrap_or_else(|err| err.into_inner()) = files
+            .paths
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner());
+        Ok(contents)
+    }
+
+    /// the file the note belongs in, with the id in its name when another note has the name
+    fn path_of(&self, contents: &Contents, note: &Note) -> PathBuf {
+        let notebooks: Vec<Notebook> = contents.notebooks.values().cloned().collect();
+        let mut folder = self.dir.clone();
+        if note.deleted_at.is_some() {
+            folder.push(TRASH);
+        }
+        folder.push(notebook_dir(&notebooks, note.notebook_id));
+        let id = note.id.unwrap_or_default();
+        let paths = self.paths.lock().unwrap_or_else(|err| err.into_inner());
+        let taken = |path: &PathBuf| paths.iter().any(|(&other, p)| other != id && p == path);
+        let path = folder.join(format!("{}.md", file_name(&note.title)));
+        if taken(&path) {
+            folder.join(format!("{} ({id}).md", file_name(&note.title)))
+        } else {
+            path
+        }
+    }
+
+    /// writes the note to its file and removes the file it had before, if it moved
+    fn write_note(&self, contents: &Contents, note: &Note) -> anyhow::Result<()> {
+        let id = note.id.context("note without id")?;
+        let path = self.path_of(contents, note);
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)
+                .with_context(|| format!("create folder {}", folder.display()))?;
+        }
+        fs::write(&path, to_markdown(note)?)
+            .with_context(|| format!("write note {}", path.display()))?;
+        let mut paths = self.paths.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(old) = paths.insert(id, path.clone()) {
+            if old != path {
+                remove(&old)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Persist for MarkdownFiles {
+    fn save(&self, contents: &Contents, changes: &Changes) -> anyhow::Result<()> {
+        if !changes.notebooks.is_empty() {
+            let notebooks: Vec<Notebook> = contents.notebooks.values().cloned().collect();
+            for &id in &changes.notebooks {
+                let folder = self.dir.join(notebook_dir(&notebooks, Some(id)));
+                fs::create_dir_all(&folder)
+                    .with_context(|| format!("create notebook {}", folder.display()))?;
+            }
+        }
+        let mut removed = Vec::new();
+        for (id, old) in &changes.notes {
+            match contents.notes.get(id) {
+                Some(note) if old.as_ref() != Some(note) => self.write_note(contents, note)?,
+                Some(_) => (),
+                None => removed.push(*id),
+            }
+        }
+        let mut paths = self.paths.lock().unwrap_or_else(|err| err.into_inner());
+        for id in removed {
+            if let Some(path) = paths.remove(&id) {
+                remove(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("remove note {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// the notebook of the folder path, `create` adds the notebooks that don't exist yet
+fn notebook_of(
+    contents: &mut Contents,
+    folder: &Path,
+    create: bool,
+) -> anyhow::Result<Option<usize>> {
+    let mut parent = None;
+    for name in folder.iter() {
+        let name = name.to_string_lossy();
+        parent = match contents.find_notebook(&name, parent) {
+            Some(id) => Some(id),
+            None if create => Some(contents.add_notebook(&name, parent)?),
+            None => return Ok(None),
+        };
+    }
+    Ok(parent)
+}
+
+/// folders and markdown files under `dir` relative to it, in a stable order. files in the
+/// trash are marked, other hidden files and folders are skipped
+#[allow(clippy::type_complexity)]
+fn walk(dir: &Path) -> anyhow::Result<(Vec<PathBuf>, Vec<(PathBuf, bool)>)> {
+    let mut folders = Vec::new();
+    let mut files = Vec::new();
+    let mut waiting = vec![(PathBuf::new(), false)];
+    while let Some((folder, trashed)) = waiting.pop() {
+        let path = dir.join(&folder);
+        let entries = fs::read_dir(&path).with_context(|| format!("read {}", path.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let relative = folder.join(&name);
+            let is_trash = folder.as_os_str().is_empty() && name == TRASH;
+            if name.to_string_lossy().starts_with('.') && !is_trash {
+                continue;
+            }
+            if entry.path().is_dir() {
+                if !trashed && !is_trash {
+                    folders.push(relative.clone());
+                }
+                waiting.push((relative, trashed || is_trash));
+            } else if relative
+                .extension()
+                .is_some_and(|ext| ext == "md" || ext == "markdown")
+            {
+                files.push((relative, trashed));
+            }
+        }
+    }
+    folders.sort();
+    files.sort();
+    Ok((folders, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileStore;
+    use crate::{repository::Note, store::NoteStore};
+    use std::fs;
+
+    #[test]
+    fn notes_are_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("work/empty")).unwrap();
+        fs::write(dir.path().join("work/by hand.md"), "no front matter").unwrap();
+
+        let store = FileStore::open(dir.path()).unwrap();
+        assert_eq!(store.get_notebooks().unwrap().len(), 2);
+        let by_hand = store.get_notes().unwrap().remove(0);
+        assert_eq!((by_hand.id, by_hand.title.as_str()), (Some(1), "by hand"));
+        let text = fs::read_to_string(dir.path().join("work/by hand.md")).unwrap();
+        assert!(text.starts_with("---\nid: 1\ntitle: by hand\n"));
+
+        let mut note = Note::new(None, "Plan".to_string(), "[[by hand]]".to_string());
+        note.notebook_id = by_hand.notebook_id;
+        let id = store.insert(&note).unwrap();
+        assert!(dir.path().join("work/Plan.md").exists());
+        let mut note = store.get_note(id).unwrap();
+        note.title = "by hand".to_string();
+        store.update(id, note).unwrap();
+        assert!(!dir.path().join("work/Plan.md").exists());
+        assert!(dir.path().join("work/by hand (2).md").exists());
+
+        store.delete(1).unwrap();
+        assert!(dir.path().join(".trash/work/by hand.md").exists());
+
+        // opened again, the files hold everything but the history
+        drop(store);
+        let store = FileStore::open(dir.path()).unwrap();
+        assert_eq!(store.get_trash().unwrap()[0].id, Some(1));
+        let note = store.get_note(2).unwrap();
+        assert_eq!(note.title, "by hand");
+        assert_eq!(store.get_links(2).unwrap()[0].target_id, Some(2));
+        store.empty_trash().unwrap();
+        assert!(!dir.path().join(".trash/work/by hand.md").exists());
+        assert_eq!(store.get_revisions(2).unwrap().len(), 1);
+    }
+}
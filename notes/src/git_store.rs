@@ -0,0 +1,374 @@
+// a markdown vault that is also a git repository. every write goes to the files like in the
+// file store, a thread commits them once the changes of `interval` are together, and when
+// the store is dropped. `sync` commits what waits, rebases the commits on the ones of the
+// remote and pushes them. git is run as a command, it has to be installed
+//
+// changes made to the files while the app was closed are committed when the store opens
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    file_store::MarkdownFiles,
+    memory_store::{Changes, Contents, MemoryStore, Persist},
+};
+
+pub type GitStore = MemoryStore<GitFiles>;
+
+/// writes the notes to the files of a git working tree and commits them
+pub struct GitFiles {
+    files: MarkdownFiles,
+    dir: PathBuf,
+    /// `None` once the store is dropped
+    commits: Option<Sender<Message>>,
+    committer: Option<JoinHandle<()>>,
+}
+
+enum Message {
+    /// what a write changed, one line per note
+    Changed(Vec<String>),
+    /// commit now, the result goes back through the sender
+    Commit(Sender<anyhow::Result<()>>),
+}
+
+/// commits exchanged with the remote by `sync`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncReport {
+    pub pulled: usize,
+    pub pushed: usize,
+}
+
+impl Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let commits = |count: usize| match count {
+            1 => "1 commit".to_string(),
+            count => format!("{count} commits"),
+        };
+        write!(
+            f,
+            "pulled {}, pushed {}",
+            commits(self.pulled),
+            commits(self.pushed)
+        )
+    }
+}
+
+impl GitStore {
+    /// reads the vault at `dir`, which becomes a git repository if it isn't one. writes are
+    /// committed together once `interval` passed since the first of them
+    pub fn open(dir: impl AsRef<Path>, interval: Duration) -> anyhow::Result<GitStore> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).with_context(|| format!("create vault {}", dir.display()))?;
+        if !dir.join(".git").exists() {
+            git(dir, &["init", "--quiet"])?;
+        }
+        let (contents, files) = MarkdownFiles::read(dir)?;
+        commit(dir, &["Commit notes changed outside the app".to_string()])?;
+        let (sender, receiver) = mpsc::channel();
+        let committer = {
+            let dir = dir.to_path_buf();
+            thread::spawn(move || commit_batches(&dir, &receiver, interval))
+        };
+        let git_files = GitFiles {
+            files,
+            dir: dir.to_path_buf(),
+            commits: Some(sender),
+            committer: Some(committer),
+        };
+        Ok(MemoryStore::with_contents(contents, git_files))
+    }
+}
+
+impl GitFiles {
+    /// commits the changes waiting for the interval
+    fn commit_now(&self) -> anyhow::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.commits
+            .as_ref()
+            .context("git store is closed")?
+            .send(Message::Commit(sender))
+            .context("git committer stopped")?;
+        receiver.recv().context("git committer stopped")?
+    }
+}
+
+impl Persist for GitFiles {
+    fn save(&self, contents: &Contents, changes: &Changes) -> anyhow::Result<()> {
+        self.files.save(contents, changes)?;
+        let changes = describe(contents, changes);
+        if let Some(commits) = &self.commits {
+            // the committer only stops with the store, the files are written either way
+            let _ = commits.send(Message::Changed(changes));
+        }
+        Ok(())
+    }
+
+    fn sync(&self, remote: &str) -> anyhow::Result<SyncReport> {
+        self.commit_now()?;
+        let dir = &self.dir;
+        let branch = git(dir, &["symbolic-ref", "--short", "HEAD"])?;
+        let branch = branch.trim();
+        let mut report = SyncReport::default();
+        // a remote without the branch gets it with the first push
+        let remote_has_branch = Command::new("git")
+            .current_dir(dir)
+            .args(["ls-remote", "--exit-code", "--heads", remote, branch])
+            .output()
+            .context("run git, is it installed?")?
+            .status
+            .success();
+        // a vault that never had a note has no commit yet
+        let born = git(dir, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok();
+        let mut ahead = "HEAD".to_string();
+        if remote_has_branch && !born {
+            git(dir, &["fetch", "--quiet", remote, branch])?;
+            report.pulled = count(dir, "FETCH_HEAD")?;
+            git(dir, &["reset", "--quiet", "--hard", "FETCH_HEAD"])?;
+            return Ok(report);
+        }
+        if !born {
+            return Ok(report);
+        }
+        if remote_has_branch {
+            git(dir, &["fetch", "--quiet", remote, branch])?;
+            report.pulled = count(dir, "HEAD..FETCH_HEAD")?;
+            if report.pulled > 0 {
+                let rebase = [&signed(dir)[..], &["rebase", "--quiet", "FETCH_HEAD"]].concat();
+                if let Err(err) = git(dir, &rebase) {
+                    let _ = git(dir, &["rebase", "--abort"]);
+                    return Err(err.context(format!(
+                        "couldn't rebase the notes on {remote}/{branch}, merge them in {} by hand",
+                        dir.display()
+                    )));
+                }
+            }
+            ahead = "FETCH_HEAD..HEAD".to_string();
+        }
+        report.pushed = count(dir, &ahead)?;
+        if report.pushed > 0 {
+            git(dir, &["push", "--quiet", remote, &format!("HEAD:{branch}")])?;
+        }
+        Ok(report)
+    }
+
+    fn reload(&self) -> anyhow::Result<Option<Contents>> {
+        self.files.reload().map(Some)
+    }
+}
+
+impl Drop for GitFiles {
+    fn drop(&mut self) {
+        // the committer commits what is left once the channel closes
+        self.commits = None;
+        if let Some(committer) = self.committer.take() {
+            let _ = committer.join();
+        }
+    }
+}
+
+/// one line for each note the write added, changed or removed
+fn describe(contents: &Contents, changes: &Changes) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (id, old) in &changes.notes {
+        let (change, note) = match (old, contents.notes.get(id)) {
+            (None, Some(note)) => ("add", note),
+            (Some(old), None) => ("purge", old),
+            (None, None) => continue,
+            (Some(old), Some(note)) if old == note => continue,
+            (Some(old), Some(note)) if old.deleted_at.is_none() && note.deleted_at.is_some() => {
+                ("trash", note)
+            }
+            (Some(old), Some(note)) if old.deleted_at.is_some() && note.deleted_at.is_none() => {
+                ("restore", note)
+            }
+            (Some(old), Some(note)) if old.title != note.title => ("rename", note),
+            (Some(_), Some(note)) => ("edit", note),
+        };
+        lines.push(format!("{change} {}", note.title));
+    }
+    for id in &changes.notebooks {
+        if let Some(notebook) = contents.notebooks.get(id) {
+            lines.push(format!("add notebook {}", notebook.name));
+        }
+    }
+    lines
+}
+
+/// commits the changes it is told about once `interval` passed since the first one
+fn commit_batches(dir: &Path, receiver: &Receiver<Message>, interval: Duration) {
+    let mut changes: Vec<String> = Vec::new();
+    let mut deadline = Instant::now();
+    loop {
+        let message = if changes.is_empty() {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        };
+        match message {
+            Ok(Message::Changed(lines)) => {
+                if changes.is_empty() {
+                    deadline = Instant::now() + interval;
+                }
+                changes.extend(lines);
+            }
+            Ok(Message::Commit(result)) => {
+                let committed = commit(dir, &changes);
+                if committed.is_ok() {
+                    changes.clear();
+                }
+                let _ = result.send(committed);
+            }
+            // a failed commit is tried again with the next batch
+            Err(RecvTimeoutError::Timeout) => {
+                if commit(dir, &changes).is_ok() {
+                    changes.clear();
+                }
+                deadline = Instant::now() + interval;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = commit(dir, &changes);
+                return;
+            }
+        }
+    }
+}
+
+/// commits every change of the working tree, nothing happens when there is none
+fn commit(dir: &Path, changes: &[String]) -> anyhow::Result<()> {
+    if git(dir, &["status", "--porcelain"])?.trim().is_empty() {
+        return Ok(());
+    }
+    git(dir, &["add", "--all"])?;
+    let message = match changes {
+        [] => "Update notes".to_string(),
+        [change] => capitalize(change),
+        changes => format!("Update {} notes\n\n{}", changes.len(), changes.join("\n")),
+    };
+    let commit = [
+        &signed(dir)[..],
+        &["commit", "--quiet", "--message", &message],
+    ]
+    .concat();
+    git(dir, &commit)?;
+    Ok(())
+}
+
+/// git refuses to commit without an author, the app signs for users who never set one
+fn signed(dir: &Path) -> Vec<&'static str> {
+    if git(dir, &["config", "user.email"]).is_ok() {
+        return Vec::new();
+    }
+    vec!["-c", "user.name=notes", "-c", "user.email=notes@localhost"]
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// commits in the revision range
+fn count(dir: &Path, range: &str) -> anyhow::Result<usize> {
+    let count = git(dir, &["rev-list", "--count", range])?;
+    count
+        .trim()
+        .parse()
+        .with_context(|| format!("git rev-list counted {count:?}"))
+}
+
+/// runs git in `dir` and returns what it printed
+fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .context("run git, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{git, GitStore};
+    use crate::{repository::Note, store::NoteStore};
+    use std::{fs, path::Path, time::Duration};
+
+    fn log(dir: &Path) -> Vec<String> {
+        let log = git(dir, &["log", "--format=%s"]).unwrap();
+        log.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn commits_and_syncs() {
+        let root = tempfile::tempdir().unwrap();
+        let (vault, remote, clone) = (
+            root.path().join("vault"),
+            root.path().join("remote.git"),
+            root.path().join("clone"),
+        );
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("by hand.md"), "text").unwrap();
+        let store = GitStore::open(&vault, Duration::from_secs(3600)).unwrap();
+        assert_eq!(log(&vault), ["Commit notes changed outside the app"]);
+
+        // writes wait for the interval, or for the store to close
+        store
+            .add(&[Note::new(None, "Plan".to_string(), String::new())])
+            .unwrap();
+        store.delete(1).unwrap();
+        assert_eq!(log(&vault).len(), 1);
+        drop(store);
+        let message = git(&vault, &["log", "-1", "--format=%B"]).unwrap();
+        assert_eq!(message.trim(), "Update 2 notes\n\nadd Plan\ntrash by hand");
+
+        fs::create_dir_all(&remote).unwrap();
+        git(&remote, &["init", "--quiet", "--bare"]).unwrap();
+        let remote_path = remote.to_str().unwrap();
+        git(&vault, &["remote", "add", "origin", remote_path]).unwrap();
+        let store = GitStore::open(&vault, Duration::ZERO).unwrap();
+        let report = store.sync("origin").unwrap();
+        assert_eq!((report.pulled, report.pushed), (0, 2));
+
+        // a commit made elsewhere comes back with the next sync, under the local ones
+        git(root.path(), &["clone", "--quiet", remote_path, "clone"]).unwrap();
+        fs::write(clone.join("Shared.md"), "from the clone").unwrap();
+        git(&clone, &["add", "--all"]).unwrap();
+        let identity = ["-c", "user.name=a", "-c", "user.email=a@b"];
+        git(
+            &clone,
+            &[&identity[..], &["commit", "-qm", "Shared"]].concat(),
+        )
+        .unwrap();
+        git(&clone, &["push", "--quiet"]).unwrap();
+        store
+            .add(&[Note::new(None, "Local".to_string(), String::new())])
+            .unwrap();
+        let report = store.sync("origin").unwrap();
+        assert_eq!((report.pulled, report.pushed), (1, 1));
+        assert!(vault.join("Shared.md").exists());
+        let titles: Vec<String> = store
+            .get_notes()
+            .unwrap()
+            .into_iter()
+            .map(|n| n.title)
+            .collect();
+        assert_eq!(titles, ["Plan", "Local", "Shared"]);
+        assert_eq!(log(&vault)[..2], ["Add Local", "Shared"]);
+    }
+}
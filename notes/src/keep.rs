@@ -0,0 +1,194 @@
+// import of google keep notes from a takeout, the `Keep` folder holds one json file per note.
+// checklists become markdown task lists and labels become tags. archived notes get the tag
+// `archived`, notes in the keep trash are left out
+
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    archive::ConversionReport,
+    repository::{utc_time, Note},
+    service::NoteService,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct KeepNote {
+    title: String,
+    text_content: String,
+    list_content: Vec<KeepItem>,
+    labels: Vec<KeepLabel>,
+    annotations: Vec<KeepAnnotation>,
+    attachments: Vec<serde_json::Value>,
+    is_pinned: bool,
+    is_archived: bool,
+    is_trashed: bool,
+    created_timestamp_usec: Option<i64>,
+    user_edited_timestamp_usec: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct KeepItem {
+    text: String,
+    is_checked: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct KeepLabel {
+    name: String,
+}
+
+/// a web link keep found in the note
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct KeepAnnotation {
+    title: String,
+    url: String,
+}
+
+/// imports the json notes of the takeout folder at `dir`
+pub fn import_keep(service: &mut NoteService, dir: &Path) -> anyhow::Result<ConversionReport> {
+    if !dir.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    let mut report = ConversionReport::default();
+    let mut notes = Vec::new();
+    for path in files {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let json =
+            fs::read_to_string(&path).with_context(|| format!("read note {}", path.display()))?;
+        let keep: KeepNote = match serde_json::from_str(&json) {
+            Ok(keep) => keep,
+            Err(err) => {
+                report
+                    .problems
+                    .push(format!("{name}: isn't a keep note ({err})"));
+                continue;
+            }
+        };
+        if keep.is_trashed {
+            report
+                .problems
+                .push(format!("{name}: is in the keep trash, left out"));
+            continue;
+        }
+        if !keep.attachments.is_empty() {
+            report.problems.push(format!(
+                "{name}: {} attachments were dropped",
+                keep.attachments.len()
+            ));
+        }
+        notes.push(to_note(keep));
+    }
+    service.import_notes(&notes)?;
+    report.notes = notes.len();
+    Ok(report)
+}
+
+fn to_note(keep: KeepNote) -> Note {
+    let mut text = keep.text_content.clone();
+    if !keep.list_content.is_empty() && !text.is_empty() {
+        text.push_str("\n\n");
+    }
+    let items: Vec<String> = keep
+        .list_content
+        .iter()
+        .map(|item| {
+            let check = if item.is_checked { 'x' } else { ' ' };
+            format!("- [{check}] {}", item.text.trim())
+        })
+        .collect();
+    text.push_str(&items.join("\n"));
+    if !keep.annotations.is_empty() {
+        text.push_str("\n\n");
+        let links: Vec<String> = keep
+            .annotations
+            .iter()
+            .map(|link| match link.title.trim() {
+                "" => format!("<{}>", link.url),
+                title => format!("[{title}]({})", link.url),
+            })
+            .collect();
+        text.push_str(&links.join("\n"));
+    }
+    let text = text.trim().to_string();
+    // keep notes often have no title, the start of their text stands in for it
+    let title = match keep.title.trim() {
+        "" => {
+            let line = text.lines().next().unwrap_or_default();
+            let line = line
+                .trim_start_matches("- [ ] ")
+                .trim_start_matches("- [x] ");
+            match line.trim() {
+                "" => "Untitled".to_string(),
+                line => line.chars().take(60).collect(),
+            }
+        }
+        title => title.to_string(),
+    };
+    let mut note = Note::new(None, title, text);
+    note.pinned = keep.is_pinned;
+    note.tags = keep.labels.into_iter().map(|label| label.name).collect();
+    if keep.is_archived {
+        note.tags.push("archived".to_string());
+    }
+    note.created_at = keep.created_timestamp_usec.map(utc_time);
+    note.updated_at = keep.user_edited_timestamp_usec.map(utc_time);
+    note
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import_keep;
+    use crate::{pool::RepositoryPool, repository::utc_time, service::NoteService};
+    use std::fs;
+
+    #[test]
+    fn takeout() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.json"),
+            r#"{"title": "", "isPinned": true, "isArchived": true, "isTrashed": false,
+                "listContent": [{"text": "milk", "isChecked": true}, {"text": "eggs", "isChecked": false}],
+                "labels": [{"name": "home"}],
+                "annotations": [{"title": "Shop", "url": "https://shop.example"}],
+                "createdTimestampUsec": 1714642200000000,
+                "userEditedTimestampUsec": 1714755731000000}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("b.json"),
+            r#"{"title": "old", "textContent": "bye", "isTrashed": true}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("a.html"), "<html></html>").unwrap();
+
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let report = import_keep(&mut service, dir.path()).unwrap();
+        assert_eq!(report.notes, 1);
+        assert_eq!(report.problems, ["b.json: is in the keep trash, left out"]);
+        let note = &service.get_all().unwrap()[0];
+        assert_eq!(note.title, "milk");
+        assert_eq!(
+            note.text,
+            "- [x] milk\n- [ ] eggs\n\n[Shop](https://shop.example)"
+        );
+        assert!(note.pinned);
+        assert_eq!(note.tags, ["archived", "home"]);
+        assert_eq!(note.created_at.as_deref(), Some("2024-05-02 09:30:00"));
+        assert_eq!(note.updated_at.as_deref(), Some("2024-05-03 17:02:11"));
+        assert_eq!(utc_time(951_782_400_000_000), "2000-02-29 00:00:00");
+    }
+}
@@ -0,0 +1,500 @@
+// maps key presses to named actions, separately for every part of the tui.
+// the defaults lean on vim, a keys file can rebind any action:
+//
+//     [notes]
+//     add_note = "i"
+//     delete = ["d", "delete"]
+//
+//     [global]
+//     quit = ["q", "ctrl-c"]
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs,
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyScope {
+    /// works everywhere unless the view binds the same key
+    Global,
+    Notes,
+    History,
+    Trash,
+    Search,
+    /// yes or no questions, like deleting a note
+    Confirm,
+    /// typing into an input, every other key is text
+    Insert,
+    /// the popup listing the profiles to switch to
+    Vaults,
+}
+
+impl KeyScope {
+    pub const ALL: [KeyScope; 8] = [
+        KeyScope::Global,
+        KeyScope::Notes,
+        KeyScope::History,
+        KeyScope::Trash,
+        KeyScope::Search,
+        KeyScope::Confirm,
+        KeyScope::Insert,
+        KeyScope::Vaults,
+    ];
+
+    /// name of the table in the keys file
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyScope::Global => "global",
+            KeyScope::Notes => "notes",
+            KeyScope::History => "history",
+            KeyScope::Trash => "trash",
+            KeyScope::Search => "search",
+            KeyScope::Confirm => "confirm",
+            KeyScope::Insert => "insert",
+            KeyScope::Vaults => "vaults",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    NextTab,
+    PreviousTab,
+    Help,
+    Vaults,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    ToggleFocus,
+    Fold,
+    AddNote,
+    EditNote,
+    NewNotebook,
+    Delete,
+    Undo,
+    Cut,
+    Paste,
+    NextLink,
+    FollowLink,
+    Back,
+    History,
+    Sort,
+    Pin,
+    ToggleRaw,
+    Restore,
+    Close,
+    Select,
+    Yes,
+    InsertMode,
+    NormalMode,
+}
+
+impl Action {
+    /// name of the action in the keys file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::Help => "help",
+            Action::Vaults => "vaults",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::ToggleFocus => "toggle_focus",
+            Action::Fold => "fold",
+            Action::AddNote => "add_note",
+            Action::EditNote => "edit_note",
+            Action::NewNotebook => "new_notebook",
+            Action::Delete => "delete",
+            Action::Undo => "undo",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::NextLink => "next_link",
+            Action::FollowLink => "follow_link",
+            Action::Back => "back",
+            Action::History => "history",
+            Action::Sort => "sort",
+            Action::Pin => "pin",
+            Action::ToggleRaw => "toggle_raw",
+            Action::Restore => "restore",
+            Action::Close => "close",
+            Action::Select => "select",
+            Action::Yes => "yes",
+            Action::InsertMode => "insert",
+            Action::NormalMode => "normal",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NextTab => "next tab",
+            Action::PreviousTab => "previous tab",
+            Action::Help => "help",
+            Action::Vaults => "switch vault",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page up",
+            Action::PageDown => "page down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::ToggleFocus => "focus content",
+            Action::Fold => "fold",
+            Action::AddNote => "add",
+            Action::EditNote => "edit",
+            Action::NewNotebook => "new notebook",
+            Action::Delete => "delete",
+            Action::Undo => "undo",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::NextLink => "next link",
+            Action::FollowLink => "follow link",
+            Action::Back => "back",
+            Action::History => "history",
+            Action::Sort => "sort",
+            Action::Pin => "pin",
+            Action::ToggleRaw => "raw/markdown",
+            Action::Restore => "restore",
+            Action::Close => "close",
+            Action::Select => "open",
+            Action::Yes => "yes",
+            Action::InsertMode => "type",
+            Action::NormalMode => "stop typing",
+        }
+    }
+
+    /// moving around is left out of the footer, the help lists it
+    fn in_footer(self) -> bool {
+        !matches!(
+            self,
+            Action::Up
+                | Action::Down
+                | Action::PageUp
+                | Action::PageDown
+                | Action::Top
+                | Action::Bottom
+                | Action::NextTab
+                | Action::PreviousTab
+        )
+    }
+}
+
+/// every action a scope knows about with its default keys
+const DEFAULTS: &[(KeyScope, Action, &[&str])] = &[
+    (KeyScope::Global, Action::Quit, &["q", "ctrl-c"]),
+    (KeyScope::Global, Action::NextTab, &["right", "L"]),
+    (KeyScope::Global, Action::PreviousTab, &["left", "H"]),
+    (KeyScope::Global, Action::Help, &["?"]),
+    (KeyScope::Global, Action::Vaults, &["v"]),
+    (KeyScope::Notes, Action::Up, &["up", "k"]),
+    (KeyScope::Notes, Action::Down, &["down", "j"]),
+    (KeyScope::Notes, Action::PageUp, &["pgup", "ctrl-u"]),
+    (KeyScope::Notes, Action::PageDown, &["pgdown", "ctrl-d"]),
+    (KeyScope::Notes, Action::Top, &["home", "g"]),
+    (KeyScope::Notes, Action::Bottom, &["end", "G"]),
+    (KeyScope::Notes, Action::ToggleFocus, &["tab"]),
+    (KeyScope::Notes, Action::Fold, &["enter"]),
+    (KeyScope::Notes, Action::AddNote, &["a"]),
+    (KeyScope::Notes, Action::EditNote, &["e"]),
+    (KeyScope::Notes, Action::NewNotebook, &["n"]),
+    (KeyScope::Notes, Action::Delete, &["d"]),
+    (KeyScope::Notes, Action::Undo, &["u"]),
+    (KeyScope::Notes, Action::Cut, &["x"]),
+    (KeyScope::Notes, Action::Paste, &["p"]),
+    (KeyScope::Notes, Action::NextLink, &["l"]),
+    (KeyScope::Notes, Action::FollowLink, &["f"]),
+    (KeyScope::Notes, Action::Back, &["b"]),
+    (KeyScope::Notes, Action::History, &["h"]),
+    (KeyScope::Notes, Action::Sort, &["s"]),
+    (KeyScope::Notes, Action::Pin, &["P"]),
+    (KeyScope::Notes, Action::ToggleRaw, &["m"]),
+    (KeyScope::History, Action::Up, &["up", "k"]),
+    (KeyScope::History, Action::Down, &["down", "j"]),
+    (KeyScope::History, Action::Restore, &["r"]),
+    (KeyScope::History, Action::Close, &["esc", "h"]),
+    (KeyScope::Trash, Action::Up, &["up", "k"]),
+    (KeyScope::Trash, Action::Down, &["down", "j"]),
+    (KeyScope::Trash, Action::Restore, &["r"]),
+    (KeyScope::Trash, Action::Delete, &["d"]),
+    (KeyScope::Trash, Action::Undo, &["u"]),
+    (KeyScope::Search, Action::InsertMode, &["i", "/"]),
+    (KeyScope::Insert, Action::NormalMode, &["esc"]),
+    (KeyScope::Confirm, Action::Yes, &["y"]),
+    (KeyScope::Vaults, Action::Up, &["up", "k"]),
+    (KeyScope::Vaults, Action::Down, &["down", "j"]),
+    (KeyScope::Vaults, Action::Select, &["enter"]),
+    (KeyScope::Vaults, Action::Close, &["esc", "v"]),
+];
+
+/// a key with its modifiers, shift is part of the character for letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn from_event(event: KeyEvent) -> Key {
+        let mut modifiers = event.modifiers;
+        if matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Key> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // a lone `-` is a key too
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                _ => bail!("unknown modifier {modifier:?} in key {text:?}"),
+            };
+            rest = key;
+        }
+        let code = match rest {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pgup" => KeyCode::PageUp,
+            "pgdown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(char), None) => KeyCode::Char(char),
+                    _ => match rest.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(number) => KeyCode::F(number),
+                        None => bail!("unknown key {text:?}"),
+                    },
+                }
+            }
+        };
+        Ok(Key { code, modifiers })
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(char) => write!(f, "{char}"),
+            KeyCode::F(number) => write!(f, "f{number}"),
+            KeyCode::Enter => f.write_str("enter"),
+            KeyCode::Esc => f.write_str("esc"),
+            KeyCode::Tab => f.write_str("tab"),
+            KeyCode::BackTab => f.write_str("backtab"),
+            KeyCode::Backspace => f.write_str("backspace"),
+            KeyCode::Delete => f.write_str("delete"),
+            KeyCode::Insert => f.write_str("insert"),
+            KeyCode::Up => f.write_str("up"),
+            KeyCode::Down => f.write_str("down"),
+            KeyCode::Left => f.write_str("left"),
+            KeyCode::Right => f.write_str("right"),
+            KeyCode::PageUp => f.write_str("pgup"),
+            KeyCode::PageDown => f.write_str("pgdown"),
+            KeyCode::Home => f.write_str("home"),
+            KeyCode::End => f.write_str("end"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub scope: KeyScope,
+    pub action: Action,
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeysValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let bindings = DEFAULTS
+            .iter()
+            .map(|&(scope, action, keys)| Binding {
+                scope,
+                action,
+                keys: keys
+                    .iter()
+                    .map(|key| Key::parse(key).expect("default keys parse"))
+                    .collect(),
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// the defaults with the bindings of the keys file on top, a missing file keeps the defaults
+    pub fn load(path: &Path) -> anyhow::Result<Keymap> {
+        let mut keymap = Keymap::default();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(keymap),
+            Err(err) => return Err(err).with_context(|| format!("read keys {}", path.display())),
+        };
+        keymap
+            .apply(&text)
+            .with_context(|| format!("keys file {}", path.display()))?;
+        Ok(keymap)
+    }
+
+    /// rebinds the actions listed in a keys file
+    pub fn apply(&mut self, text: &str) -> anyhow::Result<()> {
+        let tables: HashMap<String, HashMap<String, KeysValue>> = toml::from_str(text)?;
+        for (scope_name, actions) in tables {
+            let Some(scope) = KeyScope::ALL.into_iter().find(|s| s.name() == scope_name) else {
+                bail!("unknown section [{scope_name}]");
+            };
+            for (action_name, keys) in actions {
+                let Some(binding) = self
+                    .bindings
+                    .iter_mut()
+                    .find(|b| b.scope == scope && b.action.name() == action_name)
+                else {
+                    bail!("[{scope_name}] has no action {action_name:?}");
+                };
+                let keys = match keys {
+                    KeysValue::One(key) => vec![key],
+                    KeysValue::Many(keys) => keys,
+                };
+                binding.keys = keys
+                    .iter()
+                    .map(|key| Key::parse(key))
+                    .collect::<anyhow::Result<_>>()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// the action bound to the key in `scope`, falling back to the global bindings
+    /// except while typing
+    pub fn action(&self, scope: KeyScope, event: KeyEvent) -> Option<Action> {
+        let action = self.scope_action(scope, event);
+        if scope == KeyScope::Insert {
+            return action;
+        }
+        action.or_else(|| self.scope_action(KeyScope::Global, event))
+    }
+
+    fn scope_action(&self, scope: KeyScope, event: KeyEvent) -> Option<Action> {
+        let key = Key::from_event(event);
+        self.bindings
+            .iter()
+            .find(|binding| binding.scope == scope && binding.keys.contains(&key))
+            .map(|binding| binding.action)
+    }
+
+    pub fn bindings(&self, scope: KeyScope) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(move |binding| binding.scope == scope)
+    }
+
+    /// `key: action` for the footer of a view
+    pub fn hints(&self, scope: KeyScope) -> String {
+        let hints: Vec<String> = self
+            .bindings(scope)
+            .filter(|binding| binding.action.in_footer())
+            .filter_map(|binding| {
+                let key = binding.keys.first()?;
+                Some(format!("{key}: {}", binding.action.description()))
+            })
+            .collect();
+        format!("Press {}, ?: help", hints.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, KeyScope, Keymap};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn keymap() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut keymap = Keymap::default();
+        let notes = KeyScope::Notes;
+        assert_eq!(
+            keymap.action(notes, key(KeyCode::Char('j'))),
+            Some(Action::Down)
+        );
+        assert_eq!(
+            keymap.action(notes, key(KeyCode::Char('q'))),
+            Some(Action::Quit)
+        );
+        let shift_g = KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.action(notes, shift_g), Some(Action::Bottom));
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.action(notes, ctrl_c), Some(Action::Quit));
+
+        keymap
+            .apply("[notes]\nadd_note = \"i\"\ndelete = [\"D\", \"ctrl-x\"]\n")
+            .unwrap();
+        assert_eq!(keymap.action(notes, key(KeyCode::Char('a'))), None);
+        assert_eq!(
+            keymap.action(notes, key(KeyCode::Char('i'))),
+            Some(Action::AddNote)
+        );
+        let ctrl_x = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.action(notes, ctrl_x), Some(Action::Delete));
+
+        let insert = KeyScope::Insert;
+        assert_eq!(keymap.action(insert, key(KeyCode::Char('q'))), None);
+        assert_eq!(
+            keymap.action(insert, key(KeyCode::Esc)),
+            Some(Action::NormalMode)
+        );
+
+        assert!(keymap.apply("[notes]\nrestore = \"r\"").is_err());
+        assert!(keymap.apply("[nowhere]\nquit = \"q\"").is_err());
+        assert!(keymap.apply("[global]\nquit = \"hyper-q\"").is_err());
+    }
+}
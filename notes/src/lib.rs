@@ -1,14 +1,47 @@
 mod app;
+mod archive;
 mod backend;
+mod config;
+mod diff;
 mod editor_handler;
+mod encryption;
+mod enex;
+mod file_store;
+mod git_store;
+mod keep;
+mod keymap;
+mod links;
+mod markdown;
+mod markdown_dir;
+mod memory_store;
+mod obsidian;
+mod pool;
 mod repository;
 mod service;
+mod store;
+mod theme;
 // mod handler;
 mod ai_embedding;
 mod ui;
 mod widgets;
 
-pub use ai_embedding::{add, search};
+pub use ai_embedding::AiClient;
+pub use archive::{Archive, Conflict, ConversionReport, ImportReport};
 pub use backend::run;
-pub use repository::Repository;
-pub use service::NoteService;
+pub use config::{data_dir, default_config_path, Config, Overrides};
+pub use diff::{diff_lines, DiffLine};
+pub use encryption::{decrypt_vault, encrypt_vault, is_encrypted, EncryptedStore};
+pub use enex::import_enex;
+pub use file_store::FileStore;
+pub use git_store::{GitStore, SyncReport};
+pub use keep::import_keep;
+pub use keymap::Keymap;
+pub use markdown::render_ansi;
+pub use markdown_dir::{export_dir, import_dir};
+pub use memory_store::MemoryStore;
+pub use obsidian::import_obsidian;
+pub use pool::RepositoryPool;
+pub use repository::{Note, NoteLink, Notebook, Repository, Revision};
+pub use service::{NoteService, SortOrder, DEFAULT_TRASH_RETENTION_DAYS};
+pub use store::{NoteStore, StoreKind};
+pub use theme::{Theme, BUILTIN_THEMES, DEFAULT_THEME};
@@ -0,0 +1,87 @@
+// wiki style links between notes, `[[Note Title]]` links by title and `[[12]]` by id.
+// anything after a `|` is the label shown to the reader, `[[Note Title|see here]]`
+
+/// targets of every link in the text, in order and without duplicates
+pub fn parse_links(text: &str) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        // `[[a [[b]]` links to b
+        if let Some(nested) = inner.rfind("[[") {
+            rest = &rest[nested..];
+            continue;
+        }
+        let target = inner.split('|').next().unwrap_or_default().trim();
+        if !target.is_empty() && !inner.contains('\n') && !targets.iter().any(|t| t == target) {
+            targets.push(target.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    targets
+}
+
+/// points links to `old_target` at `new_target` and keeps their labels,
+/// `None` when nothing links to `old_target`
+pub fn rewrite_links(text: &str, old_target: &str, new_target: &str) -> Option<String> {
+    let mut rewritten = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rewritten.push_str(&rest[..start + 2]);
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        // not a link, keep looking from the next `[[`
+        if inner.contains("[[") || inner.contains('\n') {
+            continue;
+        }
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (inner, None),
+        };
+        // titles match without case, the same way the repository resolves them
+        if target.trim().eq_ignore_ascii_case(old_target) {
+            rewritten.push_str(new_target);
+            if let Some(label) = label {
+                rewritten.push('|');
+                rewritten.push_str(label);
+            }
+            changed = true;
+        } else {
+            rewritten.push_str(inner);
+        }
+        rewritten.push_str("]]");
+        rest = &rest[end + 2..];
+    }
+    rewritten.push_str(rest);
+    changed.then_some(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_links, rewrite_links};
+
+    #[test]
+    fn parse() {
+        let text = "see [[Meeting Notes]] and [[12|the list]], [[Meeting Notes]] again\n\
+                    [[ ]] [[broken\n]] [[a [[b]] [[unclosed";
+        assert_eq!(parse_links(text), ["Meeting Notes", "12", "b"]);
+    }
+
+    #[test]
+    fn rewrite() {
+        let text = "[[Old]], [[old|label]], [[Older]], [[x [[Old]] [[Old\n]]";
+        assert_eq!(
+            rewrite_links(text, "Old", "New").as_deref(),
+            Some("[[New]], [[New|label]], [[Older]], [[x [[New]] [[Old\n]]")
+        );
+        assert_eq!(rewrite_links("[[Other]]", "Old", "New"), None);
+    }
+}
@@ -0,0 +1,403 @@
+// renders the markdown of a note into styled lines for the content pane.
+// single newlines are kept as line breaks, notes are written like that more often than not.
+// fenced code is highlighted with the grammars and themes bundled in syntect, nothing is downloaded
+
+use std::sync::OnceLock;
+
+use crossterm::style::{Attribute, ContentStyle};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::{
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span, Text},
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{self, FontStyle, ThemeSet},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use crate::theme::Theme;
+
+pub fn render(markdown: &str, theme: &Theme) -> Text<'static> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer {
+        theme,
+        lines: Vec::new(),
+        spans: Vec::new(),
+        styles: Vec::new(),
+        indent: Vec::new(),
+        lists: Vec::new(),
+        links: Vec::new(),
+        code: None,
+    };
+    for event in Parser::new_ext(markdown, options) {
+        renderer.event(event);
+    }
+    renderer.flush();
+    while renderer.lines.last().is_some_and(|line| line.width() == 0) {
+        renderer.lines.pop();
+    }
+    Text::from(renderer.lines)
+}
+
+/// the markdown rendered with terminal escape codes, for printing outside the tui
+pub fn render_ansi(markdown: &str, theme: &Theme) -> String {
+    to_ansi(&render(markdown, theme))
+}
+
+fn to_ansi(text: &Text) -> String {
+    let mut ansi = String::new();
+    for line in &text.lines {
+        for span in &line.spans {
+            let style = line.style.patch(span.style);
+            let mut content = ContentStyle::new();
+            content.foreground_color = style.fg.map(Into::into);
+            content.background_color = style.bg.map(Into::into);
+            for (modifier, attribute) in [
+                (Modifier::BOLD, Attribute::Bold),
+                (Modifier::DIM, Attribute::Dim),
+                (Modifier::ITALIC, Attribute::Italic),
+                (Modifier::UNDERLINED, Attribute::Underlined),
+                (Modifier::CROSSED_OUT, Attribute::CrossedOut),
+            ] {
+                if style.add_modifier.contains(modifier) {
+                    content.attributes.set(attribute);
+                }
+            }
+            ansi.push_str(&content.apply(span.content.as_ref()).to_string());
+        }
+        ansi.push('\n');
+    }
+    ansi
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn code_themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+pub fn is_code_theme(name: &str) -> bool {
+    code_themes().themes.contains_key(name)
+}
+
+pub fn code_theme_names() -> Vec<&'static str> {
+    code_themes().themes.keys().map(String::as_str).collect()
+}
+
+/// lines of a fenced or indented code block, highlighted when the language is known
+/// and the theme has a code theme
+fn code_lines(lang: &str, code: &str, theme: &Theme) -> Vec<Vec<Span<'static>>> {
+    let plain = || {
+        code.lines()
+            .map(|line| vec![Span::styled(line.to_string(), theme.code)])
+            .collect()
+    };
+    // the info string can carry more than the language, like `rust ignore`
+    let token = lang.split_whitespace().next().unwrap_or_default();
+    let code_theme = theme
+        .code_theme
+        .as_ref()
+        .and_then(|name| code_themes().themes.get(name));
+    let (Some(syntax), Some(code_theme)) = (syntaxes().find_syntax_by_token(token), code_theme)
+    else {
+        return plain();
+    };
+    let mut highlighter = HighlightLines::new(syntax, code_theme);
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(code) {
+        let Ok(ranges) = highlighter.highlight_line(line, syntaxes()) else {
+            return plain();
+        };
+        let spans = ranges
+            .into_iter()
+            .map(|(style, text)| {
+                Span::styled(
+                    text.trim_end_matches(['\n', '\r']).to_string(),
+                    span_style(style),
+                )
+            })
+            .filter(|span| !span.content.is_empty())
+            .collect();
+        lines.push(spans);
+    }
+    lines
+}
+
+fn span_style(style: highlighting::Style) -> Style {
+    let color = style.foreground;
+    let mut span_style = Style::new().fg(Color::Rgb(color.r, color.g, color.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        span_style = span_style.bold();
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        span_style = span_style.italic();
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        span_style = span_style.underlined();
+    }
+    span_style
+}
+
+fn heading_style(level: HeadingLevel, theme: &Theme) -> Style {
+    match level {
+        HeadingLevel::H1 => Style::new().fg(theme.heading).bold().underlined(),
+        HeadingLevel::H2 => Style::new().fg(theme.heading).bold(),
+        _ => Style::new().bold(),
+    }
+}
+
+struct Renderer<'a> {
+    theme: &'a Theme,
+    lines: Vec<Line<'static>>,
+    /// spans of the line being written
+    spans: Vec<Span<'static>>,
+    /// inline styles, the innermost last
+    styles: Vec<Style>,
+    /// written in front of every line, list markers turn into spaces after their first line
+    indent: Vec<Indent>,
+    /// next number of each open list, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    /// destinations of the open links
+    links: Vec<String>,
+    /// language and text of the code block being read
+    code: Option<(String, String)>,
+}
+
+struct Indent {
+    text: String,
+    marker: bool,
+    style: Style,
+}
+
+impl Renderer<'_> {
+    fn event(&mut self, event: Event) {
+        if let Some((_, code)) = self.code.as_mut() {
+            match event {
+                Event::Text(text) => return code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => (),
+                _ => return,
+            }
+        }
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push(text.to_string()),
+            Event::Code(code) => self
+                .spans
+                .push(Span::styled(code.to_string(), self.theme.code)),
+            Event::Html(html) | Event::InlineHtml(html) => self.push(html.to_string()),
+            Event::SoftBreak | Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                self.spans
+                    .push(Span::raw("─".repeat(30)).fg(self.theme.muted));
+                self.end_block();
+            }
+            Event::TaskListMarker(done) => {
+                self.spans
+                    .push(Span::raw(if done { "[x] " } else { "[ ] " }).fg(self.theme.muted));
+            }
+            _ => (),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => self.styles.push(heading_style(level, self.theme)),
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.indent.push(Indent {
+                    text: "│ ".to_string(),
+                    marker: false,
+                    style: Style::new().fg(self.theme.muted),
+                });
+                self.styles.push(Style::new().italic());
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) => lang.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((lang, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.indent.push(Indent {
+                    text: marker,
+                    marker: true,
+                    style: Style::new().fg(self.theme.accent),
+                });
+            }
+            Tag::Emphasis => self.styles.push(Style::new().italic()),
+            Tag::Strong => self.styles.push(Style::new().bold()),
+            Tag::Strikethrough => self.styles.push(Style::new().crossed_out()),
+            Tag::Link { dest_url, .. } => {
+                self.links.push(dest_url.to_string());
+                self.styles
+                    .push(Style::new().fg(self.theme.link).underlined());
+            }
+            _ => (),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.end_block(),
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.end_block();
+            }
+            TagEnd::BlockQuote => {
+                self.flush();
+                self.indent.pop();
+                self.styles.pop();
+                self.end_block();
+            }
+            TagEnd::CodeBlock => {
+                let (lang, code) = self.code.take().unwrap_or_default();
+                for line in code_lines(&lang, &code, self.theme) {
+                    self.spans.push(Span::raw("  "));
+                    self.spans.extend(line);
+                    self.flush();
+                }
+                self.end_block();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.end_block();
+            }
+            TagEnd::Item => {
+                self.flush();
+                self.indent.pop();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                let url = self.links.pop().unwrap_or_default();
+                if !url.is_empty() {
+                    self.spans
+                        .push(Span::raw(format!(" ({url})")).fg(self.theme.muted));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn push(&mut self, text: String) {
+        let style = self
+            .styles
+            .iter()
+            .fold(Style::new(), |style, &inner| style.patch(inner));
+        self.spans.push(Span::styled(text, style));
+    }
+
+    /// ends the line being written
+    fn flush(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+        let mut spans: Vec<Span<'static>> = self
+            .indent
+            .iter()
+            .map(|indent| Span::styled(indent.text.clone(), indent.style))
+            .collect();
+        spans.append(&mut self.spans);
+        self.lines.push(Line::from(spans));
+        for indent in self.indent.iter_mut().filter(|indent| indent.marker) {
+            indent.text = " ".repeat(indent.text.chars().count());
+            indent.marker = false;
+        }
+    }
+
+    /// blocks are separated by an empty line, except inside lists
+    fn end_block(&mut self) {
+        self.flush();
+        let last_is_empty = self.lines.last().is_none_or(|line| line.width() == 0);
+        if self.lists.is_empty() && !last_is_empty {
+            self.lines.push(Line::default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_lines, render};
+    use crate::theme::Theme;
+    use ratatui::style::{Color, Modifier};
+
+    #[test]
+    fn render_blocks() {
+        let text = render(
+            "# Title\nsome **bold** and `code`\n\n- one\n- two\n  1. nested\n\n> quoted\n\n```rust\nfn main() {}\n```",
+            &Theme::default(),
+        );
+        let lines: Vec<String> = text
+            .lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "Title",
+                "",
+                "some bold and code",
+                "",
+                "• one",
+                "• two",
+                "  1. nested",
+                "",
+                "│ quoted",
+                "",
+                "  fn main() {}",
+            ]
+        );
+        assert!(text.lines[0].spans[0]
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD));
+        assert!(text.lines[2].spans[1]
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn highlight_code() {
+        let theme = Theme::default();
+        let lines = code_lines("rust", "fn main() {}\n", &theme);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].len() > 1);
+        assert!(matches!(lines[0][0].style.fg, Some(Color::Rgb(..))));
+        let plain = code_lines("no-such-language", "fn main() {}", &theme);
+        assert_eq!(plain[0].len(), 1);
+        let ansi16 = Theme::builtin("ansi16").unwrap();
+        let plain = code_lines("rust", "fn main() {}", &ansi16);
+        assert_eq!(plain[0][0].style.fg, Some(ansi16.code));
+    }
+}
@@ -0,0 +1,342 @@
+// notes as a tree of markdown files, one file per note inside the folders of its notebooks.
+// every file starts with yaml front matter, the text of the note follows it as written:
+//
+//     ---
+//     id: 12
+//     title: Meeting Notes
+//     tags:
+//     - work
+//     created: 2024-05-02 09:30:00
+//     updated: 2024-05-03 17:02:11
+//     ---
+//     Discuss project milestones
+//
+// importing such a tree updates the notes whose id still exists and adds the others. the
+// notebooks of the folders are created first, then the notes are written in one transaction
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    archive::ImportReport,
+    repository::{Note, Notebook},
+    service::NoteService,
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct FrontMatter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<usize>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) updated: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) pinned: bool,
+    /// only notes in the trash of a file store have it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) deleted: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// writes every note that isn't in the trash under `dir`, returns how many
+pub fn export_dir(service: &NoteService, dir: &Path) -> anyhow::Result<usize> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("create export directory {}", dir.display()))?;
+    let notebooks = service.get_notebooks()?;
+    let mut notes = service.get_all()?;
+    notes.sort_by_key(|note| note.id);
+    let mut written = HashSet::new();
+    for note in &notes {
+        let folder = dir.join(notebook_dir(&notebooks, note.notebook_id));
+        fs::create_dir_all(&folder)
+            .with_context(|| format!("create export directory {}", folder.display()))?;
+        let mut name = file_name(&note.title);
+        if written.contains(&folder.join(format!("{name}.md"))) {
+            name = format!("{name} ({})", note.id.unwrap_or_default());
+        }
+        let path = folder.join(format!("{name}.md"));
+        fs::write(&path, to_markdown(note)?)
+            .with_context(|| format!("write note {}", path.display()))?;
+        written.insert(path);
+    }
+    Ok(notes.len())
+}
+
+/// reads every markdown file under `dir`, the folders they are in become notebooks
+pub fn import_dir(service: &mut NoteService, dir: &Path) -> anyhow::Result<ImportReport> {
+    if !dir.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+    // notes added by this import get ids the files may use for other notes
+    let known: HashSet<usize> = service
+        .get_all()?
+        .into_iter()
+        .filter_map(|note| note.id)
+        .collect();
+    // files of notes in the trash are skipped, restoring them is up to the trash
+    let trashed: HashSet<usize> = service
+        .get_trash()?
+        .into_iter()
+        .filter_map(|note| note.id)
+        .collect();
+    let mut report = ImportReport::default();
+    let mut notes = Vec::new();
+    for path in markdown_files(dir)? {
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("read note {}", path.display()))?;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let (front, body) = from_markdown(&text, &stem)
+            .with_context(|| format!("front matter of {}", path.display()))?;
+        if front.id.is_some_and(|id| trashed.contains(&id)) {
+            report.skipped += 1;
+            continue;
+        }
+        let folder = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(dir).ok())
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let existing = front
+            .id
+            .filter(|id| known.contains(id))
+            .map(|id| service.get_note(id))
+            .transpose()?;
+        let mut note = existing.clone().unwrap_or_else(|| {
+            let mut note = Note::new(None, String::new(), String::new());
+            note.created_at = front.created.clone();
+            note.updated_at = front.updated.clone().or(front.created.clone());
+            note
+        });
+        note.title = front.title.unwrap_or_else(|| stem.to_string());
+        note.text = body.to_string();
+        note.tags = front.tags;
+        note.pinned = front.pinned;
+        match existing {
+            Some(_) => report.updated += 1,
+            None => report.created += 1,
+        }
+        notes.push((note, folder));
+    }
+    // every file is read before anything is written, so a broken one changes nothing
+    let mut upserts = Vec::with_capacity(notes.len());
+    for (mut note, folder) in notes {
+        note.notebook_id = ensure_notebook(service, &folder)?;
+        upserts.push(note);
+    }
+    service.upsert_notes(&upserts)?;
+    Ok(report)
+}
+
+pub(crate) fn to_markdown(note: &Note) -> anyhow::Result<String> {
+    let front = FrontMatter {
+        id: note.id,
+        title: Some(note.title.clone()),
+        tags: note.tags.clone(),
+        created: note.created_at.clone(),
+        updated: note.updated_at.clone(),
+        pinned: note.pinned,
+        deleted: note.deleted_at.clone(),
+    };
+    Ok(format!(
+        "---\n{}---\n{}",
+        serde_yaml::to_string(&front)?,
+        note.text
+    ))
+}
+
+/// the front matter and the text after it, a file without front matter is all text
+pub(crate) fn from_markdown<'a>(
+    text: &'a str,
+    stem: &str,
+) -> anyhow::Result<(FrontMatter, &'a str)> {
+    if !text.starts_with("---\n") {
+        return Ok((FrontMatter::default(), text));
+    }
+    let Some((yaml, body)) = split_front_matter(text) else {
+        bail!("{stem}: front matter is never closed with ---");
+    };
+    let front = if yaml.trim().is_empty() {
+        FrontMatter::default()
+    } else {
+        serde_yaml::from_str(yaml)?
+    };
+    Ok((front, body))
+}
+
+/// the yaml between the `---` lines at the start of the text and the text after it
+pub(crate) fn split_front_matter(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix("---\n")?;
+    match rest.split_once("\n---\n") {
+        Some(split) => Some(split),
+        None => rest.strip_suffix("\n---").map(|yaml| (yaml, "")),
+    }
+}
+
+/// markdown files under `dir` in a stable order, hidden files and folders are skipped
+fn markdown_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir).with_context(|| format!("read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext == "md" || ext == "markdown")
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// the notebook at the folder path, creating the notebooks that don't exist yet
+pub(crate) fn ensure_notebook(
+    service: &NoteService,
+    folder: &Path,
+) -> anyhow::Result<Option<usize>> {
+    let mut parent = None;
+    for name in folder.iter() {
+        let name = name.to_string_lossy();
+        let notebooks = service.get_notebooks()?;
+        let found = notebooks
+            .iter()
+            .find(|notebook| notebook.parent_id == parent && notebook.name == name);
+        parent = Some(match found {
+            Some(notebook) => notebook.id,
+            None => service.create_notebook(&name, parent)?,
+        });
+    }
+    Ok(parent)
+}
+
+/// folders of the notebook path, like `work/projects`
+pub(crate) fn notebook_dir(notebooks: &[Notebook], notebook_id: Option<usize>) -> PathBuf {
+    let mut names = Vec::new();
+    let mut next = notebook_id;
+    while let Some(notebook) = next.and_then(|id| notebooks.iter().find(|n| n.id == id)) {
+        names.push(file_name(&notebook.name));
+        next = notebook.parent_id;
+    }
+    names.iter().rev().collect()
+}
+
+/// the title without the characters file systems reject
+pub(crate) fn file_name(title: &str) -> String {
+    let name: String = title
+        .trim()
+        .chars()
+        .map(|char| match char {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            char if char.is_control() => '-',
+            char => char,
+        })
+        .take(100)
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        "untitled".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export_dir, import_dir};
+    use crate::{
+        archive::ImportReport,
+        pool::RepositoryPool,
+        repository::Note,
+        service::{NoteService, SortOrder},
+    };
+    use std::fs;
+
+    #[test]
+    fn round_trip() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let work = service.create_notebook("work", None).unwrap();
+        let mut note = Note::new(None, "Plan: Q3".to_string(), "\n- ship it\n".to_string());
+        note.notebook_id = Some(work);
+        note.tags = vec!["#todo".to_string(), "work".to_string()];
+        let id = service.save_note(note).unwrap();
+        let loose = service
+            .save_note(Note::new(None, "loose".to_string(), "text".to_string()))
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(export_dir(&service, dir.path()).unwrap(), 2);
+        let path = dir.path().join("work").join("Plan- Q3.md");
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(&format!(
+            "---\nid: {id}\ntitle: 'Plan: Q3'\ntags:\n- todo\n"
+        )));
+        assert!(text.ends_with("---\n\n- ship it\n"));
+
+        // an edited export updates the note, a new file adds one
+        fs::write(&path, text.replace("ship it", "shipped")).unwrap();
+        fs::write(dir.path().join("work").join("new.md"), "no front matter").unwrap();
+        let report = import_dir(&mut service, dir.path()).unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                created: 1,
+                updated: 2,
+                skipped: 0
+            }
+        );
+        let note = service.get_note(id).unwrap();
+        assert_eq!(note.text, "\n- shipped\n");
+        assert_eq!(note.tags, ["todo", "work"]);
+        let titles: Vec<String> = service
+            .get_notes_in(Some(work), SortOrder::Title)
+            .unwrap()
+            .into_iter()
+            .map(|note| note.title)
+            .collect();
+        assert_eq!(titles, ["new", "Plan: Q3"]);
+
+        // into an empty store everything is new, notebooks are created from the folders
+        let mut other = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let report = import_dir(&mut other, dir.path()).unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                created: 3,
+                updated: 0,
+                skipped: 0
+            }
+        );
+        assert!(other.find_notebook("work").is_ok());
+
+        // the file of a note in the trash doesn't bring it back
+        service.delete_note(loose).unwrap();
+        let report = import_dir(&mut service, dir.path()).unwrap();
+        assert_eq!(report.skipped, 1);
+        assert_eq!(service.get_trash().unwrap().len(), 1);
+    }
+}
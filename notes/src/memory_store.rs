@@ -0,0 +1,850 @@
+// a store that keeps every note in memory, for tests and as the core of the file store.
+// it behaves like the sqlite repository: ids of purged notes aren't handed out again, links
+// resolve by id or by title ignoring ascii case, and a revision is saved whenever the title
+// or the text changes
+//
+// a write changes the contents in place and keeps the old value of everything it touched.
+// once it succeeded the `Persist` of the store saves what changed, when either fails the old
+// values go back, so a failed write changes nothing
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    archive::{Archive, ArchiveNote, Conflict, ImportReport},
+    git_store::SyncReport,
+    links::parse_links,
+    repository::{normalize_tag, utc_time, Note, NoteLink, Notebook, Revision},
+    store::NoteStore,
+};
+
+/// everything a memory store holds
+#[derive(Debug, Default)]
+pub struct Contents {
+    pub(crate) notes: BTreeMap<usize, Note>,
+    pub(crate) notebooks: BTreeMap<usize, Notebook>,
+    /// oldest first
+    pub(crate) revisions: HashMap<usize, Vec<Revision>>,
+    /// highest id a note ever had
+    pub(crate) last_note_id: usize,
+    pub(crate) last_notebook_id: usize,
+    /// what the running write changed so far
+    changes: Changes,
+}
+
+/// the old value of everything a write touched, enough to undo it
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// notes as they were before the write, `None` for the ones it added
+    pub(crate) notes: BTreeMap<usize, Option<Note>>,
+    /// notebooks the write added
+    pub(crate) notebooks: Vec<usize>,
+    pub(crate) revisions: HashMap<usize, OldRevisions>,
+    last_note_id: usize,
+    last_notebook_id: usize,
+}
+
+/// the revisions of a note before a write
+#[derive(Debug)]
+pub(crate) enum OldRevisions {
+    /// the write only appended to them, they were this many
+    Appended(usize),
+    /// the write replaced or removed them, these were there before
+    Replaced(Option<Vec<Revision>>),
+}
+
+/// keeps the contents of a memory store after every write
+pub trait Persist: Send + Sync {
+    /// called with the contents after a write and what it changed, an error cancels the write
+    fn save(&self, contents: &Contents, changes: &Changes) -> anyhow::Result<()>;
+
+    /// exchanges what was saved with a remote, see `NoteStore::sync`
+    fn sync(&self, _remote: &str) -> anyhow::Result<SyncReport> {
+        bail!("only git stores can sync")
+    }
+
+    /// the contents as saved, after a sync brought in changes. `None` keeps the ones in memory
+    fn reload(&self) -> anyhow::Result<Option<Contents>> {
+        Ok(None)
+    }
+}
+
+/// nothing is kept, the notes are gone with the store
+#[derive(Debug, Default)]
+pub struct Volatile;
+
+impl Persist for Volatile {
+    fn save(&self, _: &Contents, _: &Changes) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore<P: Persist = Volatile> {
+    contents: Mutex<Contents>,
+    persist: P,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl<P: Persist> MemoryStore<P> {
+    pub(crate) fn with_contents(mut contents: Contents, persist: P) -> MemoryStore<P> {
+        // reading the contents may have added notebooks, they are saved already
+        contents.changes = Changes::default();
+        MemoryStore {
+            contents: Mutex::new(contents),
+            persist,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Contents> {
+        self.contents.lock().unwrap_or_else(|err| {
+            // a write panicked halfway, what it changed so far goes back
+            let mut contents = PoisonError::into_inner(err);
+            contents.undo();
+            self.contents.clear_poison();
+            contents
+        })
+    }
+
+    fn read<T>(&self, work: impl FnOnce(&Contents) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        work(&self.lock())
+    }
+
+    fn write<T>(&self, work: impl FnOnce(&mut Contents) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut contents = self.lock();
+        contents.changes.last_note_id = contents.last_note_id;
+        contents.changes.last_notebook_id = contents.last_notebook_id;
+        let result = work(&mut contents);
+        let result = result.and_then(|result| {
+            self.persist.save(&contents, &contents.changes)?;
+            Ok(result)
+        });
+        if result.is_ok() {
+            contents.changes = Changes::default();
+        } else {
+            contents.undo();
+        }
+        result
+    }
+}
+
+/// the current utc time, as the repository writes it
+pub(crate) fn now() -> String {
+    let usec = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    utc_time(usec as i64)
+}
+
+/// what a link target resolves to, built once for all the links it resolves
+struct Resolver {
+    ids: HashSet<usize>,
+    /// the lowest id of each trimmed title, ascii lowercase
+    titles: HashMap<String, usize>,
+}
+
+impl Resolver {
+    fn resolve(&self, target: &str) -> Option<usize> {
+        if target.bytes().all(|byte| byte.is_ascii_digit()) {
+            let id = target.parse().ok()?;
+            return self.ids.contains(&id).then_some(id);
+        }
+        self.titles.get(&target.to_ascii_lowercase()).copied()
+    }
+}
+
+impl Contents {
+    fn note(&self, note_id: usize) -> anyhow::Result<&Note> {
+        self.notes
+            .get(&note_id)
+            .with_context(|| format!("note {note_id} doesn't exist"))
+    }
+
+    fn note_mut(&mut self, note_id: usize) -> anyhow::Result<&mut Note> {
+        self.touch_note(note_id);
+        self.notes
+            .get_mut(&note_id)
+            .with_context(|| format!("note {note_id} doesn't exist"))
+    }
+
+    /// keeps the note as it was before the write, the first time the write changes it
+    fn touch_note(&mut self, note_id: usize) {
+        if !self.changes.notes.contains_key(&note_id) {
+            let old = self.notes.get(&note_id).cloned();
+            self.changes.notes.insert(note_id, old);
+        }
+    }
+
+    /// sets the revisions of the note, `None` removes them
+    fn replace_revisions(&mut self, note_id: usize, revisions: Option<Vec<Revision>>) {
+        let old = match revisions {
+            Some(revisions) => self.revisions.insert(note_id, revisions),
+            None => self.revisions.remove(&note_id),
+        };
+        match self.changes.revisions.entry(note_id) {
+            Entry::Vacant(entry) => {
+                entry.insert(OldRevisions::Replaced(old));
+            }
+            Entry::Occupied(mut entry) => {
+                if let OldRevisions::Appended(len) = *entry.get() {
+                    let old = old.map(|mut old| {
+                        old.truncate(len);
+                        old
+                    });
+                    entry.insert(OldRevisions::Replaced(old));
+                }
+            }
+        }
+    }
+
+    /// puts back what the running write changed
+    fn undo(&mut self) {
+        let changes = std::mem::take(&mut self.changes);
+        for (id, note) in changes.notes {
+            match note {
+                Some(note) => self.notes.insert(id, note),
+                None => self.notes.remove(&id),
+            };
+        }
+        for id in changes.notebooks {
+            self.notebooks.remove(&id);
+        }
+        for (id, revisions) in changes.revisions {
+            match revisions {
+                OldRevisions::Appended(0) | OldRevisions::Replaced(None) => {
+                    self.revisions.remove(&id);
+                }
+                OldRevisions::Appended(len) => {
+                    if let Some(revisions) = self.revisions.get_mut(&id) {
+                        revisions.truncate(len);
+                    }
+                }
+                OldRevisions::Replaced(Some(revisions)) => {
+                    self.revisions.insert(id, revisions);
+                }
+            }
+        }
+        self.last_note_id = changes.last_note_id;
+        self.last_notebook_id = changes.last_notebook_id;
+    }
+
+    /// notes outside the trash, by id
+    fn alive(&self) -> impl Iterator<Item = &Note> {
+        self.notes.values().filter(|note| note.deleted_at.is_none())
+    }
+
+    fn resolver(&self) -> Resolver {
+        let mut titles = HashMap::new();
+        for note in self.alive() {
+            let id = note.id.unwrap_or_default();
+            titles
+                .entry(note.title.trim().to_ascii_lowercase())
+                .or_insert(id);
+        }
+        Resolver {
+            ids: self.alive().filter_map(|note| note.id).collect(),
+            titles,
+        }
+    }
+
+    fn links(&self, note: &Note, resolver: &Resolver) -> Vec<NoteLink> {
+        parse_links(&note.text)
+            .into_iter()
+            .map(|target| NoteLink {
+                source_id: note.id.unwrap_or_default(),
+                target_id: resolver.resolve(&target),
+                target,
+            })
+            .collect()
+    }
+
+    fn check_notebook(&self, notebook_id: Option<usize>) -> anyhow::Result<()> {
+        match notebook_id {
+            Some(id) if !self.notebooks.contains_key(&id) => {
+                bail!("notebook {id} doesn't exist")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// inserts the note with its id, or the next one when it has none
+    fn insert_note(&mut self, note: &Note) -> anyhow::Result<usize> {
+        let id = match note.id {
+            Some(id) if self.notes.contains_key(&id) => bail!("note {id} already exists"),
+            Some(id) => id,
+            None => self.last_note_id + 1,
+        };
+        self.check_notebook(note.notebook_id)?;
+        self.last_note_id = self.last_note_id.max(id);
+        let created = note.created_at.clone().unwrap_or_else(now);
+        let mut stored = note.clone();
+        stored.id = Some(id);
+        stored.deleted_at = None;
+        stored.updated_at = note.updated_at.clone().or(Some(created.clone()));
+        stored.created_at = Some(created);
+        stored.tags = normalize_tags(&note.tags);
+        self.touch_note(id);
+        self.notes.insert(id, stored);
+        self.write_revision(id);
+        Ok(id)
+    }
+
+    fn update_note(&mut self, note_id: usize, note: &Note) -> anyhow::Result<()> {
+        self.check_notebook(note.notebook_id)?;
+        let stored = self.note_mut(note_id)?;
+        // only a new title or text counts as an edit
+        if stored.title != note.title || stored.text != note.text {
+            stored.updated_at = Some(now());
+        }
+        stored.title.clone_from(&note.title);
+        stored.text.clone_from(&note.text);
+        stored.notebook_id = note.notebook_id;
+        stored.pinned = note.pinned;
+        stored.tags = normalize_tags(&note.tags);
+        self.write_revision(note_id);
+        Ok(())
+    }
+
+    /// appends the note as its newest revision, unless its title and text didn't change
+    fn write_revision(&mut self, note_id: usize) {
+        let Some(note) = self.notes.get(&note_id) else {
+            return;
+        };
+        let revisions = self.revisions.entry(note_id).or_default();
+        let len = revisions.len();
+        self.changes
+            .revisions
+            .entry(note_id)
+            .or_insert(OldRevisions::Appended(len));
+        let revision = match revisions.last() {
+            Some(last) if last.title == note.title && last.text == note.text => return,
+            Some(last) => last.revision + 1,
+            None => 1,
+        };
+        revisions.push(Revision {
+            note_id,
+            revision,
+            title: note.title.clone(),
+            text: note.text.clone(),
+            created_at: now(),
+        });
+    }
+
+    fn remove_note(&mut self, note_id: usize) {
+        self.touch_note(note_id);
+        self.notes.remove(&note_id);
+        self.replace_revisions(note_id, None);
+    }
+
+    fn purge_where(&mut self, purge: impl Fn(&str) -> bool) -> usize {
+        let ids: Vec<usize> = self
+            .notes
+            .iter()
+            .filter(|(_, note)| note.deleted_at.as_deref().is_some_and(&purge))
+            .map(|(&id, _)| id)
+            .collect();
+        for &id in &ids {
+            self.remove_note(id);
+        }
+        ids.len()
+    }
+
+    pub(crate) fn add_notebook(
+        &mut self,
+        name: &str,
+        parent_id: Option<usize>,
+    ) -> anyhow::Result<usize> {
+        self.check_notebook(parent_id)?;
+        self.last_notebook_id += 1;
+        let id = self.last_notebook_id;
+        self.changes.notebooks.push(id);
+        self.notebooks.insert(
+            id,
+            Notebook {
+                id,
+                name: name.to_string(),
+                parent_id,
+            },
+        );
+        Ok(id)
+    }
+
+    /// the notebook named `name` inside `parent`, the oldest one if there are several
+    pub(crate) fn find_notebook(&self, name: &str, parent_id: Option<usize>) -> Option<usize> {
+        self.notebooks
+            .values()
+            .find(|notebook| notebook.name == name && notebook.parent_id == parent_id)
+            .map(|notebook| notebook.id)
+    }
+
+    fn restore_archive(
+        &mut self,
+        archive: &Archive,
+        conflict: Conflict,
+    ) -> anyhow::Result<ImportReport> {
+        // finds or creates every archived notebook, parents first
+        let mut notebook_ids = HashMap::new();
+        let mut waiting: Vec<_> = archive.notebooks.iter().collect();
+        while !waiting.is_empty() {
+            let (mut ready, rest): (Vec<_>, Vec<_>) = waiting.into_iter().partition(|notebook| {
+                notebook
+                    .parent_id
+                    .is_none_or(|id| notebook_ids.contains_key(&id))
+            });
+            waiting = rest;
+            if ready.is_empty() {
+                // their parents aren't in the archive, they go to the top level
+                ready = std::mem::take(&mut waiting);
+            }
+            for notebook in ready {
+                let parent = notebook
+                    .parent_id
+                    .and_then(|id| notebook_ids.get(&id).copied());
+                let id = match self.find_notebook(&notebook.name, parent) {
+                    Some(id) => id,
+                    None if self.notebooks.contains_key(&notebook.id) => {
+                        self.add_notebook(&notebook.name, parent)?
+                    }
+                    None => {
+                        self.last_notebook_id = self.last_notebook_id.max(notebook.id);
+                        self.changes.notebooks.push(notebook.id);
+                        self.notebooks.insert(
+                            notebook.id,
+                            Notebook {
+                                id: notebook.id,
+                                name: notebook.name.clone(),
+                                parent_id: parent,
+                            },
+                        );
+                        notebook.id
+                    }
+                };
+                notebook_ids.insert(notebook.id, id);
+            }
+        }
+        let taken: HashSet<usize> = self.notes.keys().copied().collect();
+        let mut report = ImportReport::default();
+        // new ids are handed out last, so they can't take the id of an archived note
+        let mut renumbered = Vec::new();
+        for note in &archive.notes {
+            let notebook_id = note
+                .notebook_id
+                .and_then(|id| notebook_ids.get(&id).copied());
+            if !taken.contains(&note.id) {
+                self.insert_archived(Some(note.id), note, notebook_id);
+                report.created += 1;
+                continue;
+            }
+            match conflict {
+                Conflict::Skip => report.skipped += 1,
+                Conflict::Overwrite => {
+                    self.remove_note(note.id);
+                    self.insert_archived(Some(note.id), note, notebook_id);
+                    report.updated += 1;
+                }
+                Conflict::KeepBoth => renumbered.push((note, notebook_id)),
+            }
+        }
+        for (note, notebook_id) in renumbered {
+            self.insert_archived(None, note, notebook_id);
+            report.created += 1;
+        }
+        Ok(report)
+    }
+
+    /// stores an archived note as it was, `None` gives it a new id
+    fn insert_archived(
+        &mut self,
+        id: Option<usize>,
+        note: &ArchiveNote,
+        notebook_id: Option<usize>,
+    ) {
+        let id = id.unwrap_or(self.last_note_id + 1);
+        self.last_note_id = self.last_note_id.max(id);
+        let created = note.created_at.clone().unwrap_or_else(now);
+        let mut stored = Note::new(Some(id), note.title.clone(), note.text.clone());
+        stored.notebook_id = notebook_id;
+        stored.pinned = note.pinned;
+        stored.tags = normalize_tags(&note.tags);
+        stored.updated_at = note.updated_at.clone().or(Some(created.clone()));
+        stored.created_at = Some(created);
+        stored.deleted_at.clone_from(&note.deleted_at);
+        self.touch_note(id);
+        self.notes.insert(id, stored);
+        if note.revisions.is_empty() {
+            self.write_revision(id);
+            return;
+        }
+        let revisions = note
+            .revisions
+            .iter()
+            .map(|revision| Revision {
+                note_id: id,
+                revision: revision.revision,
+                title: revision.title.clone(),
+                text: revision.text.clone(),
+                created_at: revision.created_at.clone(),
+            })
+            .collect();
+        self.replace_revisions(id, Some(revisions));
+    }
+}
+
+/// sorted and without duplicates, the way the repository returns tags
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags.iter().filter_map(|tag| normalize_tag(tag)).collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+impl<P: Persist> NoteStore for MemoryStore<P> {
+    fn get_notes(&self) -> anyhow::Result<Vec<Note>> {
+        self.read(|contents| Ok(contents.alive().cloned().collect()))
+    }
+
+    fn get_note(&self, note_id: usize) -> anyhow::Result<Note> {
+        self.read(|contents| contents.note(note_id).cloned())
+    }
+
+    fn add(&self, notes: &[Note]) -> anyhow::Result<Vec<Note>> {
+        self.write(|contents| {
+            let ids = notes
+                .iter()
+                .map(|note| contents.insert_note(note))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(ids.iter().map(|id| contents.notes[id].clone()).collect())
+        })
+    }
+
+    fn upsert(&self, notes: &[Note]) -> anyhow::Result<Vec<Note>> {
+        self.write(|contents| {
+            let mut ids = Vec::with_capacity(notes.len());
+            for note in notes {
+                let id = match note.id.filter(|id| contents.notes.contains_key(id)) {
+                    Some(id) => {
+                        contents.update_note(id, note)?;
+                        id
+                    }
+                    None => contents.insert_note(note)?,
+                };
+                ids.push(id);
+            }
+            Ok(ids.iter().map(|id| contents.notes[id].clone()).collect())
+        })
+    }
+
+    fn insert(&self, note: &Note) -> anyhow::Result<usize> {
+        let mut note = note.clone();
+        // the id of a new note is always picked by the store
+        note.id = None;
+        self.write(|contents| contents.insert_note(&note))
+    }
+
+    fn delete(&self, note_id: usize) -> anyhow::Result<Note> {
+        self.write(|contents| {
+            let note = contents.note_mut(note_id)?;
+            if note.deleted_at.is_some() {
+                bail!("note {note_id} is already in the trash");
+            }
+            note.deleted_at = Some(now());
+            Ok(note.clone())
+        })
+    }
+
+    fn restore(&self, note_id: usize) -> anyhow::Result<()> {
+        self.write(|contents| {
+            let note = contents.note_mut(note_id)?;
+            if note.deleted_at.take().is_none() {
+                bail!("note {note_id} isn't in the trash");
+            }
+            Ok(())
+        })
+    }
+
+    fn get_trash(&self) -> anyhow::Result<Vec<Note>> {
+        self.read(|contents| {
+            let mut trash: Vec<Note> = contents
+                .notes
+                .values()
+                .filter(|note| note.deleted_at.is_some())
+                .cloned()
+                .collect();
+            trash.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(b.id.cmp(&a.id)));
+            Ok(trash)
+        })
+    }
+
+    fn purge(&self, note_id: usize) -> anyhow::Result<()> {
+        self.write(|contents| {
+            if contents.note(note_id)?.deleted_at.is_none() {
+                bail!("note {note_id} isn't in the trash");
+            }
+            contents.remove_note(note_id);
+            Ok(())
+        })
+    }
+
+    fn empty_trash(&self) -> anyhow::Result<usize> {
+        self.write(|contents| Ok(contents.purge_where(|_| true)))
+    }
+
+    fn purge_older_than(&self, days: u32) -> anyhow::Result<usize> {
+        let usec = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as i64;
+        let cutoff = utc_time(usec - i64::from(days) * 86_400_000_000);
+        // nothing to write most of the time, the store isn't saved then
+        let expired = self.read(|contents| {
+            Ok(contents
+                .notes
+                .values()
+                .any(|note| note.deleted_at.as_ref().is_some_and(|at| *at < cutoff)))
+        })?;
+        if !expired {
+            return Ok(0);
+        }
+        self.write(|contents| Ok(contents.purge_where(|deleted_at| deleted_at < cutoff.as_str())))
+    }
+
+    fn update(&self, note_id: usize, note: Note) -> anyhow::Result<()> {
+        self.write(|contents| contents.update_note(note_id, &note))
+    }
+
+    fn update_all(&self, notes: &[Note]) -> anyhow::Result<()> {
+        self.write(|contents| {
+            for note in notes {
+                let note_id = note.id.context("note without id")?;
+                contents.update_note(note_id, note)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn get_links(&self, note_id: usize) -> anyhow::Result<Vec<NoteLink>> {
+        self.read(|contents| {
+            let Some(note) = contents.notes.get(&note_id) else {
+                return Ok(Vec::new());
+            };
+            Ok(contents.links(note, &contents.resolver()))
+        })
+    }
+
+    fn get_backlinks(&self, note_id: usize) -> anyhow::Result<Vec<Note>> {
+        self.read(|contents| {
+            let resolver = contents.resolver();
+            let mut notes: Vec<Note> = contents
+                .alive()
+                .filter(|note| {
+                    contents
+                        .links(note, &resolver)
+                        .iter()
+                        .any(|link| link.target_id == Some(note_id))
+                })
+                .cloned()
+                .collect();
+            notes.sort_by(|a, b| a.title.cmp(&b.title));
+            Ok(notes)
+        })
+    }
+
+    fn get_revisions(&self, note_id: usize) -> anyhow::Result<Vec<Revision>> {
+        self.read(|contents| {
+            Ok(contents
+                .revisions
+                .get(&note_id)
+                .cloned()
+                .unwrap_or_default())
+        })
+    }
+
+    fn get_revision(&self, note_id: usize, revision: usize) -> anyhow::Result<Revision> {
+        self.read(|contents| {
+            contents
+                .revisions
+                .get(&note_id)
+                .and_then(|revisions| revisions.iter().find(|r| r.revision == revision))
+                .cloned()
+                .with_context(|| format!("note {note_id} has no revision {revision}"))
+        })
+    }
+
+    fn get_broken_links(&self) -> anyhow::Result<Vec<NoteLink>> {
+        self.read(|contents| {
+            let resolver = contents.resolver();
+            Ok(contents
+                .alive()
+                .flat_map(|note| contents.links(note, &resolver))
+                .filter(|link| link.target_id.is_none())
+                .collect())
+        })
+    }
+
+    fn move_note(&self, note_id: usize, notebook_id: Option<usize>) -> anyhow::Result<()> {
+        self.write(|contents| {
+            contents.check_notebook(notebook_id)?;
+            contents.note_mut(note_id)?.notebook_id = notebook_id;
+            Ok(())
+        })
+    }
+
+    fn set_pinned(&self, note_id: usize, pinned: bool) -> anyhow::Result<()> {
+        self.write(|contents| {
+            let note = contents.note_mut(note_id)?;
+            if note.deleted_at.is_some() {
+                bail!("note {note_id} is in the trash");
+            }
+            note.pinned = pinned;
+            Ok(())
+        })
+    }
+
+    fn restore_archive(
+        &self,
+        archive: &Archive,
+        conflict: Conflict,
+    ) -> anyhow::Result<ImportReport> {
+        self.write(|contents| contents.restore_archive(archive, conflict))
+    }
+
+    fn add_notebook(&self, name: &str, parent_id: Option<usize>) -> anyhow::Result<usize> {
+        self.write(|contents| contents.add_notebook(name, parent_id))
+    }
+
+    fn get_notebook(&self, notebook_id: usize) -> anyhow::Result<Option<Notebook>> {
+        self.read(|contents| Ok(contents.notebooks.get(&notebook_id).cloned()))
+    }
+
+    fn sync(&self, remote: &str) -> anyhow::Result<SyncReport> {
+        // no write gets in between the commit of the last writes and the push
+        let mut contents = self.lock();
+        let report = self.persist.sync(remote)?;
+        if report.pulled > 0 {
+            if let Some(reloaded) = self.persist.reload()? {
+                *contents = reloaded;
+            }
+        }
+        Ok(report)
+    }
+
+    fn get_notebooks(&self) -> anyhow::Result<Vec<Notebook>> {
+        self.read(|contents| {
+            let mut notebooks: Vec<Notebook> = contents.notebooks.values().cloned().collect();
+            notebooks.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+            Ok(notebooks)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStore;
+    use crate::{repository::Note, store::NoteStore};
+    use anyhow::bail;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn behaves_like_the_repository() {
+        let store = MemoryStore::new();
+        let notes = store
+            .add(&[
+                Note::new(
+                    None,
+                    "Plan".to_string(),
+                    "see [[todo]] and [[9]]".to_string(),
+                ),
+                Note::new(None, " TODO ".to_string(), "[[1]]".to_string()),
+            ])
+            .unwrap();
+        assert_eq!(notes[1].id, Some(2));
+        let links = store.get_links(1).unwrap();
+        assert_eq!(links[0].target_id, Some(2));
+        assert_eq!(store.get_broken_links().unwrap()[0].target, "9");
+        assert_eq!(store.get_backlinks(1).unwrap()[0].title, " TODO ");
+
+        // only a new title or text is an edit with a revision
+        let mut note = store.get_note(1).unwrap();
+        note.tags = vec!["#b".to_string(), "a".to_string(), "a".to_string()];
+        store.update(1, note.clone()).unwrap();
+        assert_eq!(store.get_revisions(1).unwrap().len(), 1);
+        assert_eq!(store.get_note(1).unwrap().tags, ["a", "b"]);
+        note.text = "[[todo]]".to_string();
+        store.update(1, note).unwrap();
+        assert_eq!(store.get_revision(1, 2).unwrap().text, "[[todo]]");
+
+        // trashed notes don't resolve links, purged ids aren't reused
+        store.delete(2).unwrap();
+        assert_eq!(store.get_links(1).unwrap()[0].target_id, None);
+        assert_eq!(store.get_links(2).unwrap()[0].target_id, Some(1));
+        assert!(store.set_pinned(2, true).is_err());
+        assert_eq!(store.purge_older_than(1).unwrap(), 0);
+        assert_eq!(store.empty_trash().unwrap(), 1);
+        let id = store
+            .insert(&Note::new(Some(1), "c".to_string(), String::new()))
+            .unwrap();
+        assert_eq!(id, 3);
+
+        // a failed write changes nothing
+        let mut bad = Note::new(Some(1), "x".to_string(), String::new());
+        bad.notebook_id = Some(5);
+        let good = Note::new(Some(3), "renamed".to_string(), String::new());
+        assert!(store.update_all(&[good, bad.clone()]).is_err());
+        assert_eq!(store.get_note(3).unwrap().title, "c");
+        assert_eq!(store.get_revisions(3).unwrap().len(), 1);
+        let new = Note::new(None, "new".to_string(), String::new());
+        bad.id = None;
+        assert!(store.add(&[new.clone(), bad]).is_err());
+        assert!(store.get_note(4).is_err());
+        assert_eq!(store.add(&[new]).unwrap()[0].id, Some(4));
+    }
+
+    #[test]
+    fn failed_writes_are_undone() {
+        let store = MemoryStore::new();
+        let work = store.add_notebook("work", None).unwrap();
+        let mut note = Note::new(None, "a".to_string(), String::new());
+        note.notebook_id = Some(work);
+        store.add(&[note.clone()]).unwrap();
+        note.id = Some(1);
+        note.text = "b".to_string();
+        store.update(1, note).unwrap();
+
+        let failed: anyhow::Result<()> = store.write(|contents| {
+            contents.add_notebook("home", None)?;
+            contents.remove_note(1);
+            contents.insert_note(&Note::new(None, "c".to_string(), String::new()))?;
+            bail!("stop")
+        });
+        assert!(failed.is_err());
+        assert_eq!(store.get_revisions(1).unwrap().len(), 2);
+        assert_eq!(store.get_notebooks().unwrap().len(), 1);
+        assert_eq!(store.add_notebook("home", None).unwrap(), work + 1);
+        let id = store
+            .insert(&Note::new(None, "c".to_string(), String::new()))
+            .unwrap();
+        assert_eq!(id, 2);
+
+        // a write that panics halfway is undone by the next lock
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            store.write(|contents| -> anyhow::Result<()> {
+                contents.remove_note(1);
+                panic!("stop")
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(store.get_note(1).unwrap().text, "b");
+        assert_eq!(store.get_revisions(1).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,446 @@
+// import of an obsidian vault. every markdown file becomes a note titled after its file name,
+// in the notebook of its folder. the tags of its front matter and its `#tags` become tags and
+// wikilinks keep working, since notes link by title:
+//
+//     [[Folder/Meeting Notes#Agenda|agenda]]  ->  [[Meeting Notes|agenda]]
+//     ![[diagram.png]]                        ->  ![diagram.png](<attachments>/diagram.png)
+//     [see](Meeting%20Notes.md)               ->  [[Meeting Notes|see]]
+//
+// the other files of the vault are attachments, they are copied with their folders. whatever
+// has no place in a note, like headings in links or front matter fields, ends up in the report
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use pulldown_cmark::{Event, Parser, Tag};
+use serde_yaml::Value;
+
+use crate::{
+    archive::ConversionReport,
+    markdown_dir::{ensure_notebook, split_front_matter},
+    repository::Note,
+    service::NoteService,
+};
+
+/// the files of a vault by their path relative to it
+struct Vault {
+    notes: Vec<PathBuf>,
+    attachments: Vec<PathBuf>,
+    /// lowercase file stems of the notes
+    titles: HashSet<String>,
+}
+
+/// imports every note of the vault at `vault`, its attachments are copied into `attachments`
+pub fn import_obsidian(
+    service: &mut NoteService,
+    vault: &Path,
+    attachments: &Path,
+) -> anyhow::Result<ConversionReport> {
+    if !vault.is_dir() {
+        bail!("{} is not a directory", vault.display());
+    }
+    let mut report = ConversionReport::default();
+    let files = vault_files(vault, &mut report)?;
+    let attachments =
+        std::path::absolute(attachments).context("attachments directory of the import")?;
+    for path in &files.attachments {
+        let target = attachments.join(path);
+        if let Some(folder) = target.parent() {
+            fs::create_dir_all(folder)
+                .with_context(|| format!("create attachment directory {}", folder.display()))?;
+        }
+        fs::copy(vault.join(path), &target)
+            .with_context(|| format!("copy attachment {}", path.display()))?;
+        report.attachments += 1;
+    }
+
+    let mut imported = HashMap::new();
+    for path in &files.notes {
+        let text = fs::read_to_string(vault.join(path))
+            .with_context(|| format!("read note {}", path.display()))?;
+        let mut problems = Vec::new();
+        let (mut tags, body) = front_matter(&text, &mut problems);
+        let (text, inline_tags) = convert(body, path, &files, &attachments, &mut problems);
+        tags.extend(inline_tags);
+        let title = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut note = Note::new(None, title.to_string(), text);
+        note.notebook_id = ensure_notebook(service, path.parent().unwrap_or(Path::new("")))?;
+        note.tags = tags.into_iter().collect();
+        let id = service.save_note(note)?;
+        imported.insert(id, path);
+        report.notes += 1;
+        let path = path.display();
+        report.problems.extend(
+            problems
+                .into_iter()
+                .map(|problem| format!("{path}: {problem}")),
+        );
+    }
+
+    // links are resolved once every note is in, to the vault or to the notes already there
+    for link in service.get_broken_links()? {
+        if let Some(path) = imported.get(&link.source_id) {
+            report.problems.push(format!(
+                "{}: no note is called {:?}",
+                path.display(),
+                link.target
+            ));
+        }
+    }
+    Ok(report)
+}
+
+/// notes and attachments of the vault in a stable order, hidden folders like `.obsidian`
+/// and `.trash` are left out
+fn vault_files(vault: &Path, report: &mut ConversionReport) -> anyhow::Result<Vault> {
+    let mut files = Vault {
+        notes: Vec::new(),
+        attachments: Vec::new(),
+        titles: HashSet::new(),
+    };
+    let mut dirs = vec![vault.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir).with_context(|| format!("read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(vault)?.to_path_buf();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("md") => {
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    files.titles.insert(stem.to_lowercase());
+                    files.notes.push(relative);
+                }
+                Some("canvas") => report.problems.push(format!(
+                    "{}: canvases can't be imported",
+                    relative.display()
+                )),
+                _ => files.attachments.push(relative),
+            }
+        }
+    }
+    files.notes.sort();
+    files.attachments.sort();
+    Ok(files)
+}
+
+/// the tags of the front matter and the text after it. the other fields are dropped
+fn front_matter<'a>(text: &'a str, problems: &mut Vec<String>) -> (BTreeSet<String>, &'a str) {
+    let mut tags = BTreeSet::new();
+    let Some((yaml, body)) = split_front_matter(text) else {
+        return (tags, text);
+    };
+    let fields = match serde_yaml::from_str::<Value>(yaml) {
+        Ok(Value::Mapping(fields)) => fields,
+        Ok(Value::Null) => return (tags, body),
+        Ok(_) => {
+            problems.push("front matter isn't a list of fields, kept it in the text".to_string());
+            return (tags, text);
+        }
+        Err(err) => {
+            problems.push(format!(
+                "front matter is invalid ({err}), kept it in the text"
+            ));
+            return (tags, text);
+        }
+    };
+    for (key, value) in fields {
+        match key.as_str() {
+            Some("tags" | "tag") => match value {
+                Value::Sequence(values) => tags.extend(values.iter().filter_map(yaml_string)),
+                value => tags.extend(yaml_string(&value).iter().flat_map(|tags| {
+                    tags.split([',', ' '])
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })),
+            },
+            Some(key) => problems.push(format!("front matter field {key:?} was dropped")),
+            None => problems.push("front matter field without a name was dropped".to_string()),
+        }
+    }
+    (tags, body)
+}
+
+fn yaml_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// the text with its links rewritten, and its `#tags`
+fn convert(
+    text: &str,
+    path: &Path,
+    vault: &Vault,
+    attachments: &Path,
+    problems: &mut Vec<String>,
+) -> (String, BTreeSet<String>) {
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    // neither links nor tags are looked for in code
+    let mut skipped: Vec<Range<usize>> = Vec::new();
+    let folder = path.parent().unwrap_or(Path::new(""));
+    for (event, range) in Parser::new(text).into_offset_iter() {
+        match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => skipped.push(range),
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                let source = &text[range.clone()];
+                if let Some(edit) = markdown_link(source, &dest_url, folder, vault, attachments) {
+                    edits.push((range.clone(), edit));
+                }
+                skipped.push(range);
+            }
+            _ => {}
+        }
+    }
+    let in_skipped = |at: usize| skipped.iter().any(|range| range.contains(&at));
+
+    let mut from = 0;
+    while let Some(start) = text[from..].find("[[").map(|start| start + from) {
+        let Some(end) = text[start..].find("]]").map(|end| end + start) else {
+            break;
+        };
+        from = start + 2;
+        let inner = &text[start + 2..end];
+        if in_skipped(start) || inner.contains('\n') || inner.contains("[[") {
+            continue;
+        }
+        let embed = start > 0 && text.as_bytes()[start - 1] == b'!';
+        let range = if embed { start - 1 } else { start }..end + 2;
+        edits.push((range, wikilink(inner, embed, vault, attachments, problems)));
+        from = end + 2;
+    }
+
+    let mut tags = BTreeSet::new();
+    for (at, _) in text.match_indices('#') {
+        let starts_word = text[..at]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        if !starts_word || in_skipped(at) {
+            continue;
+        }
+        let tag: String = text[at + 1..]
+            .chars()
+            .take_while(|char| char.is_alphanumeric() || matches!(char, '_' | '-' | '/'))
+            .collect();
+        // `#1` is a number, not a tag
+        if tag.chars().any(|char| !char.is_ascii_digit()) {
+            tags.insert(tag);
+        }
+    }
+
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut converted = String::with_capacity(text.len());
+    let mut written = 0;
+    for (range, replacement) in edits {
+        if range.start < written {
+            continue;
+        }
+        converted.push_str(&text[written..range.start]);
+        converted.push_str(&replacement);
+        written = range.end;
+    }
+    converted.push_str(&text[written..]);
+    (converted, tags)
+}
+
+/// `[[target#heading|label]]` as a link of this app, or as a markdown link to an attachment
+fn wikilink(
+    inner: &str,
+    embed: bool,
+    vault: &Vault,
+    attachments: &Path,
+    problems: &mut Vec<String>,
+) -> String {
+    let (target, label) = match inner.split_once('|') {
+        Some((target, label)) => (target.trim(), Some(label.trim())),
+        None => (inner.trim(), None),
+    };
+    let (target, anchor) = match target.split_once(['#', '^']) {
+        Some((target, anchor)) => (target.trim(), Some(anchor)),
+        None => (target, None),
+    };
+    if let Some(attachment) = find_attachment(vault, Path::new(""), target) {
+        let label = label.unwrap_or(target);
+        let bang = if embed { "!" } else { "" };
+        return format!("{bang}[{label}]({})", destination(attachments, attachment));
+    }
+    let title = note_title(target);
+    if embed {
+        problems.push(format!("embedded note {title:?} became a link"));
+    }
+    if let Some(anchor) = anchor.filter(|anchor| !anchor.is_empty()) {
+        problems.push(format!("link to {title:?} lost its part {anchor:?}"));
+    }
+    match label {
+        Some(label) => format!("[[{title}|{label}]]"),
+        None => format!("[[{title}]]"),
+    }
+}
+
+/// `[label](Other%20Note.md)` as a link of this app, links to attachments point at their copy.
+/// `None` leaves the link as it is
+fn markdown_link(
+    source: &str,
+    dest_url: &str,
+    folder: &Path,
+    vault: &Vault,
+    attachments: &Path,
+) -> Option<String> {
+    if dest_url.contains("://") || dest_url.starts_with(['#', '/']) || dest_url.is_empty() {
+        return None;
+    }
+    let dest = percent_decode(dest_url.split('#').next().unwrap_or_default());
+    let label_end = source.rfind("](")?;
+    if dest.ends_with(".md") {
+        let title = note_title(&dest);
+        if !vault.titles.contains(&title.to_lowercase()) {
+            return None;
+        }
+        let label = source[..label_end].trim_start_matches(['!', '[']);
+        return Some(match label {
+            "" => format!("[[{title}]]"),
+            label if label == title => format!("[[{title}]]"),
+            label => format!("[[{title}|{label}]]"),
+        });
+    }
+    let attachment = find_attachment(vault, folder, &dest)?;
+    Some(format!(
+        "{}]({})",
+        &source[..label_end],
+        destination(attachments, attachment)
+    ))
+}
+
+/// the attachment a link names, relative to the note, to the vault or by its file name alone
+fn find_attachment<'a>(vault: &'a Vault, folder: &Path, target: &str) -> Option<&'a PathBuf> {
+    let target = Path::new(target);
+    [folder.join(target), target.to_path_buf()]
+        .iter()
+        .find_map(|path| vault.attachments.iter().find(|file| *file == path))
+        .or_else(|| {
+            let name = target.file_name()?;
+            vault
+                .attachments
+                .iter()
+                .find(|file| file.file_name() == Some(name))
+        })
+}
+
+/// the note a link target names, `Folder/Meeting Notes.md` is `Meeting Notes`
+fn note_title(target: &str) -> &str {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    name.strip_suffix(".md").unwrap_or(name)
+}
+
+/// where the copy of an attachment is, in angle brackets when the path has spaces
+fn destination(attachments: &Path, attachment: &Path) -> String {
+    let path = attachments.join(attachment).display().to_string();
+    if path.contains(' ') {
+        format!("<{path}>")
+    } else {
+        path
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut at = 0;
+    while at < bytes.len() {
+        let hex = bytes
+            .get(at + 1..at + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[at], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                at += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                at += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import_obsidian;
+    use crate::{
+        pool::RepositoryPool,
+        service::{NoteService, SortOrder},
+    };
+    use std::fs;
+
+    #[test]
+    fn vault() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        fs::create_dir_all(root.join(".obsidian")).unwrap();
+        fs::create_dir_all(root.join("Work/assets")).unwrap();
+        fs::write(root.join(".obsidian/app.json"), "{}").unwrap();
+        fs::write(root.join("Work/assets/diagram.png"), "png").unwrap();
+        fs::write(root.join("board.canvas"), "{}").unwrap();
+        fs::write(
+            root.join("Work/Plan.md"),
+            "---\ntags: [project, \"#q3\"]\naliases: [roadmap]\n---\n\
+             # Plan #draft\n![[diagram.png]] see [[Ideas#Later|later]], [the ideas](Ideas.md)\n\
+             and [[Missing]]. `#code` issue #12\n",
+        )
+        .unwrap();
+        fs::write(root.join("Ideas.md"), "tags: none\n![[Plan]]").unwrap();
+
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let attachments = tempfile::tempdir().unwrap();
+        let report = import_obsidian(&mut service, root, attachments.path()).unwrap();
+        assert_eq!(report.to_string(), "2 notes, 1 attachments, 5 problems");
+        assert_eq!(
+            report.problems,
+            [
+                "board.canvas: canvases can't be imported",
+                "Ideas.md: embedded note \"Plan\" became a link",
+                "Work/Plan.md: front matter field \"aliases\" was dropped",
+                "Work/Plan.md: link to \"Ideas\" lost its part \"Later\"",
+                "Work/Plan.md: no note is called \"Missing\"",
+            ]
+        );
+        let copy = attachments.path().join("Work/assets/diagram.png");
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "png");
+
+        let work = service.find_notebook("Work").unwrap();
+        let plan = &service
+            .get_notes_in(Some(work.id), SortOrder::Title)
+            .unwrap()[0];
+        assert_eq!(plan.tags, ["draft", "project", "q3"]);
+        assert_eq!(
+            plan.text,
+            format!(
+                "# Plan #draft\n![diagram.png]({}) see [[Ideas|later]], [[Ideas|the ideas]]\n\
+                 and [[Missing]]. `#code` issue #12\n",
+                copy.display()
+            )
+        );
+        let links = service.get_links(plan.id.unwrap()).unwrap();
+        assert!(links[0].target_id.is_some());
+    }
+}
@@ -0,0 +1,146 @@
+// a pool of connections to one database, so the cli, the tui and a server can use it at
+// the same time. every connection runs in WAL mode, readers never wait for the writer and a
+// writer waits up to the busy timeout for another one. async code hands its queries to the
+// blocking threads of tokio with `run`, the other methods block and belong outside the executor
+
+use std::{
+    ops::DerefMut,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context;
+use r2d2::{ManageConnection, Pool};
+use rusqlite::Connection;
+
+use crate::repository::Repository;
+
+/// connections kept open, more than one thread rarely touches the database at once
+const POOL_SIZE: u32 = 4;
+
+/// how long getting a connection waits for one to be free
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub struct RepositoryPool {
+    pool: Pool<Connector>,
+}
+
+/// opens the connections of a pool on a database that is already migrated
+#[derive(Debug)]
+struct Connector {
+    /// a path, or a `file:` uri for a shared database in memory
+    target: PathBuf,
+}
+
+impl ManageConnection for Connector {
+    type Connection = Repository;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Repository, rusqlite::Error> {
+        Repository::connect(Connection::open(&self.target)?)
+    }
+
+    fn is_valid(&self, repository: &mut Repository) -> Result<(), rusqlite::Error> {
+        repository.ping()
+    }
+
+    fn has_broken(&self, _: &mut Repository) -> bool {
+        false
+    }
+}
+
+impl RepositoryPool {
+    /// opens the database, creating it and its directory on first use
+    pub fn open(db_path: impl AsRef<Path>) -> anyhow::Result<RepositoryPool> {
+        let db_path = db_path.as_ref();
+        // creates and migrates the database before the pool connects to it
+        Repository::new(db_path)?;
+        let pool = Pool::builder()
+            .max_size(POOL_SIZE)
+            .connection_timeout(CONNECTION_TIMEOUT)
+            .build(Connector {
+                target: db_path.to_path_buf(),
+            })
+            .with_context(|| format!("open database {}", db_path.display()))?;
+        Ok(RepositoryPool { pool })
+    }
+
+    /// database that lives as long as the pool, nothing is written to disk
+    pub fn in_memory() -> anyhow::Result<RepositoryPool> {
+        static DATABASES: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "file:notes-{}-{}?mode=memory&cache=shared",
+            std::process::id(),
+            DATABASES.fetch_add(1, Ordering::Relaxed)
+        );
+        // the database is gone once its last connection closes, this one keeps it until
+        // the pool is connected and the pool keeps its connections open
+        let migrated = Repository::new(&name)?;
+        let pool = Pool::builder()
+            .max_size(POOL_SIZE)
+            .connection_timeout(CONNECTION_TIMEOUT)
+            .max_lifetime(None)
+            .idle_timeout(None)
+            .build(Connector {
+                target: PathBuf::from(name),
+            })
+            .context("open database in memory")?;
+        drop(migrated);
+        Ok(RepositoryPool { pool })
+    }
+
+    /// a connection for blocking code, it goes back to the pool when dropped
+    pub fn get(&self) -> anyhow::Result<impl DerefMut<Target = Repository>> {
+        self.pool.get().context("database connection")
+    }
+
+    /// runs `work` with a connection on a blocking thread of tokio
+    pub async fn run<T, F>(&self, work: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Repository) -> anyhow::Result<T> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut repository = pool.get().context("database connection")?;
+            work(&mut repository)
+        })
+        .await
+        .context("database task")?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RepositoryPool;
+    use crate::repository::Note;
+    use rusqlite::Connection;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.db");
+        let pool = RepositoryPool::open(&path).unwrap();
+        let tasks: Vec<_> = (0..8)
+            .map(|n| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    let note = Note::new(None, format!("note {n}"), String::new());
+                    pool.run(move |db| Ok(db.add(&[note])?)).await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(pool.get().unwrap().get_notes().unwrap().len(), 8);
+
+        let mode: String = Connection::open(&path)
+            .unwrap()
+            .pragma_query_value(None, "journal_mode", |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+    }
+}
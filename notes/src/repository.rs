@@ -1,26 +1,164 @@
 use anyhow::{self, Context};
-use rusqlite::{self, params, Connection, Result};
-use std::{fmt::Display, path::Path};
+use rusqlite::{
+    self, params, Connection, OptionalExtension, Result, Row, Transaction, TransactionBehavior,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    ai_embedding::AiClient,
+    archive::{Archive, ArchiveNote, ArchiveNotebook, ArchiveRevision, Conflict, ImportReport},
+    links::parse_links,
+};
+
+/// schema changes, applied in order. the position of a migration (starting at 1) is the
+/// `user_version` the database has once it ran, so only append to this list!
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS note (
+        id   INTEGER PRIMARY KEY AUTOINCREMENT,
+        title TEXT NOT NULL,
+        text TEXT NOT NULL
+    );",
+    "CREATE TABLE notebook (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        parent_id INTEGER REFERENCES notebook(id) ON DELETE CASCADE
+    );
+    ALTER TABLE note ADD COLUMN notebook_id INTEGER REFERENCES notebook(id) ON DELETE SET NULL;",
+    "CREATE TABLE note_link (
+        source_id INTEGER NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+        target TEXT NOT NULL,
+        target_id INTEGER,
+        PRIMARY KEY (source_id, target)
+    );",
+    "CREATE TABLE note_revision (
+        note_id INTEGER NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+        revision INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        text TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        PRIMARY KEY (note_id, revision)
+    );
+    INSERT INTO note_revision (note_id, revision, title, text) SELECT id, 1, title, text FROM note;",
+    "ALTER TABLE note ADD COLUMN deleted_at TEXT;",
+    "ALTER TABLE note ADD COLUMN created_at TEXT;
+    ALTER TABLE note ADD COLUMN updated_at TEXT;
+    UPDATE note SET
+        created_at = COALESCE(
+            (SELECT MIN(created_at) FROM note_revision WHERE note_id = note.id), datetime('now')),
+        updated_at = COALESCE(
+            (SELECT MAX(created_at) FROM note_revision WHERE note_id = note.id), datetime('now'));",
+    "ALTER TABLE note ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE note_tag (
+        note_id INTEGER NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (note_id, tag)
+    );",
+    "CREATE TABLE vault_key (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        key TEXT NOT NULL
+    );",
+];
+
+/// tags can't hold whitespace, so they come back joined by spaces
+const NOTE_COLUMNS: &str =
+    "id, title, text, notebook_id, deleted_at, created_at, updated_at, pinned,
+    (SELECT group_concat(tag, ' ') FROM note_tag WHERE note_id = note.id)";
+
+/// points the links a write of note ?1 could have changed at the notes they name: the links
+/// of the note and the ones naming its id, its title before the write (?2) or its title now.
+/// links to missing or trashed notes get a NULL `target_id`, a NULL ?1 points every link
+const RESOLVE_LINKS: &str = "UPDATE note_link SET target_id = CASE
+        WHEN target NOT GLOB '*[^0-9]*'
+            THEN (SELECT id FROM note
+                  WHERE id = CAST(note_link.target AS INTEGER) AND deleted_at IS NULL)
+        ELSE (SELECT id FROM note
+              WHERE trim(title) = note_link.target COLLATE NOCASE AND deleted_at IS NULL
+              ORDER BY id LIMIT 1)
+    END
+    WHERE ?1 IS NULL
+        OR source_id = ?1
+        OR target = CAST(?1 AS TEXT)
+        OR target = trim(?2) COLLATE NOCASE
+        OR target = (SELECT trim(title) FROM note WHERE id = ?1) COLLATE NOCASE";
+
+/// how long a write waits for the writes of other connections before it fails
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Repository {
     db: Connection,
 }
 
 impl Repository {
+    /// opens the database, creating it and its directory on first use
     pub fn new(db_path: impl AsRef<Path>) -> anyhow::Result<Repository> {
-        let db = Connection::open(db_path)?;
+        let db_path = db_path.as_ref();
+        if let Some(dir) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create database directory {}", dir.display()))?;
+        }
+        let db = Connection::open(db_path)
+            .with_context(|| format!("open database {}", db_path.display()))?;
+        Repository::from_connection(db)
+    }
+
+    /// database that lives as long as the repository, nothing is written to disk
+    pub fn in_memory() -> anyhow::Result<Repository> {
+        Repository::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(db: Connection) -> anyhow::Result<Repository> {
+        let mut repository = Repository::connect(db)?;
+        repository.migrate().context("migrate database schema")?;
+        Ok(repository)
+    }
+
+    /// sets up a connection to a database that is already migrated
+    pub(crate) fn connect(db: Connection) -> Result<Repository> {
+        db.pragma_update(None, "foreign_keys", true)?;
+        db.busy_timeout(BUSY_TIMEOUT)?;
+        // readers don't wait for the writer, the writer doesn't wait for readers
+        db.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
         Ok(Repository { db })
     }
 
+    /// fails when the connection is no longer usable
+    pub(crate) fn ping(&self) -> Result<()> {
+        self.db.execute_batch("")
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        // holds the write lock from the start, so another process opening the database at
+        // the same time waits for the migrations instead of running them twice
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version: usize =
+            transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index + 1)?;
+        }
+        if version < 3 {
+            // note_link is new, fill it from the notes we already have
+            let mut stmt = transaction.prepare("SELECT id, text FROM note")?;
+            let notes = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<(usize, String)>>>()?;
+            for (id, text) in notes {
+                write_links(&transaction, id, &text)?;
+            }
+            transaction.execute(RESOLVE_LINKS, params![None::<usize>, None::<String>])?;
+        }
+        transaction.commit()
+    }
+
     pub fn initialize_db(&mut self) -> anyhow::Result<()> {
-        self.db.execute(
-            "CREATE TABLE IF NOT EXISTS note (
-                id   INTEGER PRIMARY KEY AUTOINCREMENT,
-                title TEXT NOT NULL,
-                text TEXT NOT NULL
-            )",
-            (),
-        )?;
         // insert test notes!
         self.insert_test_notes()?;
 
@@ -40,20 +178,19 @@ impl Repository {
         (9, "Learning Goals", "1. Master Python programming, 2. Learn data visualization techniques, 3. Understand machine learning algorithms, 4. Get proficient in SQL and databases, 5. Study cloud computing and AWS services"),
         (10, "Home Improvement Projects", "1. Paint the living room, 2. Install new kitchen cabinets, 3. Replace old windows, 4. Build a deck in the backyard, 5. Update the bathroom fixtures")
     ].into_iter().map(|el| Note::new(Some(el.0), el.1.to_string(), el.2.to_string())).collect();
-        self.add(&notes).context("Add test notes!")
+        self.add(&notes).context("Add test notes!")?;
+        Ok(())
     }
-    pub async fn insert_test_notes_ai(&self) -> anyhow::Result<()> {
+    pub async fn insert_test_notes_ai(&self, ai: &AiClient) -> anyhow::Result<()> {
         let notes = self.get_notes()?;
-        crate::add(&notes)
-            .await
-            .context("add test notes to ai engine!")
+        ai.add(&notes).await.context("add test notes to ai engine!")
     }
 
     pub fn get_notes(&self) -> Result<Vec<Note>> {
-        let mut stmt = self.db.prepare("SELECT id, title, text FROM note")?;
-        let notes_iter = stmt.query_map([], |row| {
-            Ok(Note::new(row.get(0)?, row.get(1)?, row.get(2)?))
-        })?;
+        let mut stmt = self.db.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM note WHERE deleted_at IS NULL"
+        ))?;
+        let notes_iter = stmt.query_map([], note_from_row)?;
         let mut notes = Vec::new();
         for note in notes_iter {
             notes.push(note?);
@@ -61,58 +198,768 @@ impl Repository {
         Ok(notes)
     }
 
-    pub fn add(&mut self, notes: &Vec<Note>) -> Result<()> {
-        let transaction = self.db.transaction()?;
-        {
-            let mut stmt =
-                transaction.prepare("INSERT INTO note (id, title, text) VALUES (?, ?, ?)")?;
-            for note in notes {
-                stmt.execute(params![note.id.unwrap(), note.title, note.text])?;
-            }
+    /// inserts the notes in one transaction and returns them as stored, sqlite picks the ids
+    /// of notes without one
+    pub fn add(&mut self, notes: &[Note]) -> Result<Vec<Note>> {
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let ids = notes
+            .iter()
+            .map(|note| insert_note(&transaction, note))
+            .collect::<Result<Vec<_>>>()?;
+        let stored = read_notes(&transaction, &ids)?;
+        transaction.commit()?;
+        Ok(stored)
+    }
+
+    /// updates the notes whose id is stored and inserts the others, keeping the ids they
+    /// carry. all in one transaction, returns the notes as stored
+    pub fn upsert(&mut self, notes: &[Note]) -> Result<Vec<Note>> {
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut ids = Vec::with_capacity(notes.len());
+        for note in notes {
+            let stored = match note.id {
+                Some(id) => transaction
+                    .query_row("SELECT id FROM note WHERE id = ?1", [id], |row| row.get(0))
+                    .optional()?,
+                None => None,
+            };
+            let id = match stored {
+                Some(id) => {
+                    update_note(&transaction, id, note)?;
+                    id
+                }
+                None => insert_note(&transaction, note)?,
+            };
+            ids.push(id);
         }
+        let stored = read_notes(&transaction, &ids)?;
         transaction.commit()?;
-        Ok(())
+        Ok(stored)
+    }
+
+    /// inserts a single new note and returns the id sqlite assigned to it
+    pub fn insert(&self, note: &Note) -> Result<usize> {
+        let transaction = Transaction::new_unchecked(&self.db, TransactionBehavior::Immediate)?;
+        let id = insert_note(
+            &transaction,
+            &Note {
+                id: None,
+                ..note.clone()
+            },
+        )?;
+        transaction.commit()?;
+        Ok(id)
     }
 
     pub fn get_note(&self, note_id: usize) -> Result<Note> {
-        let mut stmt = self
-            .db
-            .prepare("SELECT id, title, text FROM note WHERE id = ?1")?;
-        let mut note_iter = stmt.query_map([note_id], |row| {
-            Ok(Note::new(row.get(0)?, row.get(1)?, row.get(2)?))
-        })?;
-        note_iter
-            .next()
-            .ok_or_else(|| rusqlite::Error::QueryReturnedNoRows)?
-            .and_then(|r| Ok(r))
+        self.db.query_row(
+            &format!("SELECT {NOTE_COLUMNS} FROM note WHERE id = ?1"),
+            [note_id],
+            note_from_row,
+        )
     }
 
+    /// moves the note to the trash, it keeps its links and revisions until it is purged
     pub fn delete(&self, note_id: usize) -> Result<Note> {
-        let note = self.get_note(note_id)?;
+        let transaction = Transaction::new_unchecked(&self.db, TransactionBehavior::Immediate)?;
+        let deleted = transaction.execute(
+            "UPDATE note SET deleted_at = datetime('now') WHERE id = ?1 AND deleted_at IS NULL",
+            params![note_id],
+        )?;
+        if deleted == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        // links to the deleted note are broken now
+        resolve_links(&transaction, note_id, None)?;
+        transaction.commit()?;
+        self.get_note(note_id)
+    }
+
+    /// takes the note out of the trash
+    pub fn restore(&self, note_id: usize) -> Result<()> {
+        let transaction = Transaction::new_unchecked(&self.db, TransactionBehavior::Immediate)?;
+        let restored = transaction.execute(
+            "UPDATE note SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![note_id],
+        )?;
+        if restored == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        resolve_links(&transaction, note_id, None)?;
+        transaction.commit()
+    }
+
+    /// notes in the trash, the most recently deleted first
+    pub fn get_trash(&self) -> Result<Vec<Note>> {
+        let mut stmt = self.db.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM note WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC, id DESC"
+        ))?;
+        let notes = stmt.query_map([], note_from_row)?;
+        notes.collect()
+    }
+
+    /// deletes a note in the trash for good, with its links and revisions
+    pub fn purge(&self, note_id: usize) -> Result<()> {
+        let purged = self.db.execute(
+            "DELETE FROM note WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![note_id],
+        )?;
+        if purged == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// purges every note in the trash, returns how many
+    pub fn empty_trash(&self) -> Result<usize> {
         self.db
-            .execute("DELETE FROM note WHERE id = ?1", params![note_id])?;
-        Ok(note)
+            .execute("DELETE FROM note WHERE deleted_at IS NOT NULL", ())
+    }
+
+    /// purges the notes that are in the trash for more than `days`, returns how many
+    pub fn purge_older_than(&self, days: u32) -> Result<usize> {
+        self.db.execute(
+            "DELETE FROM note WHERE deleted_at < datetime('now', ?1)",
+            [format!("-{days} days")],
+        )
     }
 
-    // Placeholder for the update function if needed
-    pub fn update(&self, _note_id: usize, _new_note: Note) -> Result<()> {
+    pub fn update(&self, note_id: usize, new_note: Note) -> Result<()> {
+        let transaction = Transaction::new_unchecked(&self.db, TransactionBehavior::Immediate)?;
+        update_note(&transaction, note_id, &new_note)?;
+        transaction.commit()
+    }
+
+    /// updates all the notes or none of them, every note needs an id
+    pub fn update_all(&self, notes: &[Note]) -> Result<()> {
+        let transaction = Transaction::new_unchecked(&self.db, TransactionBehavior::Immediate)?;
+        for note in notes {
+            let note_id = note.id.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            update_note(&transaction, note_id, note)?;
+        }
+        transaction.commit()
+    }
+
+    /// links written in the note, broken ones have no `target_id`
+    pub fn get_links(&self, note_id: usize) -> Result<Vec<NoteLink>> {
+        let mut stmt = self.db.prepare(
+            "SELECT source_id, target, target_id FROM note_link WHERE source_id = ?1 ORDER BY rowid",
+        )?;
+        let links = stmt.query_map([note_id], link_from_row)?;
+        links.collect()
+    }
+
+    /// notes that link to the note
+    pub fn get_backlinks(&self, note_id: usize) -> Result<Vec<Note>> {
+        let mut stmt = self.db.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM note
+            WHERE id IN (SELECT source_id FROM note_link WHERE target_id = ?1)
+                AND deleted_at IS NULL
+            ORDER BY title"
+        ))?;
+        let notes = stmt.query_map([note_id], note_from_row)?;
+        notes.collect()
+    }
+
+    /// every saved version of the note, oldest first
+    pub fn get_revisions(&self, note_id: usize) -> Result<Vec<Revision>> {
+        let mut stmt = self.db.prepare(
+            "SELECT note_id, revision, title, text, created_at FROM note_revision
+            WHERE note_id = ?1 ORDER BY revision",
+        )?;
+        let revisions = stmt.query_map([note_id], revision_from_row)?;
+        revisions.collect()
+    }
+
+    pub fn get_revision(&self, note_id: usize, revision: usize) -> Result<Revision> {
+        self.db.query_row(
+            "SELECT note_id, revision, title, text, created_at FROM note_revision
+            WHERE note_id = ?1 AND revision = ?2",
+            [note_id, revision],
+            revision_from_row,
+        )
+    }
+
+    /// links that point to a note which doesn't exist
+    pub fn get_broken_links(&self) -> Result<Vec<NoteLink>> {
+        let mut stmt = self.db.prepare(
+            "SELECT source_id, target, target_id FROM note_link
+            WHERE target_id IS NULL
+                AND source_id IN (SELECT id FROM note WHERE deleted_at IS NULL)
+            ORDER BY source_id, rowid",
+        )?;
+        let links = stmt.query_map([], link_from_row)?;
+        links.collect()
+    }
+
+    /// puts the note in the notebook, `None` moves it to the top level
+    pub fn move_note(&self, note_id: usize, notebook_id: Option<usize>) -> Result<()> {
+        let updated = self.db.execute(
+            "UPDATE note SET notebook_id = ?1 WHERE id = ?2",
+            params![notebook_id, note_id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
         Ok(())
     }
+
+    /// pinning doesn't count as an edit, `updated_at` stays the same
+    pub fn set_pinned(&self, note_id: usize, pinned: bool) -> Result<()> {
+        let updated = self.db.execute(
+            "UPDATE note SET pinned = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+            params![pinned, note_id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// writes the notebooks and notes of an archive in one transaction. notebooks are matched
+    /// by their path, notes by their id, `conflict` decides about the ids already taken
+    pub fn restore_archive(
+        &mut self,
+        archive: &Archive,
+        conflict: Conflict,
+    ) -> Result<ImportReport> {
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let notebook_ids = restore_notebooks(&transaction, &archive.notebooks)?;
+        let taken: HashSet<usize> = {
+            let mut stmt = transaction.prepare("SELECT id FROM note")?;
+            let ids = stmt.query_map([], |row| row.get(0))?;
+            ids.collect::<Result<_>>()?
+        };
+        let mut report = ImportReport::default();
+        // new ids are handed out last, so they can't take the id of an archived note
+        let mut renumbered = Vec::new();
+        for note in &archive.notes {
+            let notebook_id = note
+                .notebook_id
+                .and_then(|id| notebook_ids.get(&id).copied());
+            if !taken.contains(&note.id) {
+                insert_archived(&transaction, Some(note.id), note, notebook_id)?;
+                report.created += 1;
+                continue;
+            }
+            match conflict {
+                Conflict::Skip => report.skipped += 1,
+                Conflict::Overwrite => {
+                    // its links, tags and revisions go with it
+                    remove_note(&transaction, note.id)?;
+                    insert_archived(&transaction, Some(note.id), note, notebook_id)?;
+                    report.updated += 1;
+                }
+                Conflict::KeepBoth => renumbered.push((note, notebook_id)),
+            }
+        }
+        for (note, notebook_id) in renumbered {
+            insert_archived(&transaction, None, note, notebook_id)?;
+            report.created += 1;
+        }
+        transaction.commit()?;
+        Ok(report)
+    }
+
+    /// writes the archived notes over the stored ones with their ids, history included.
+    /// `vault_key` replaces the key of an encrypted vault, `None` leaves it unencrypted. all in
+    /// one transaction, encrypting and decrypting write through it
+    pub fn replace_notes(&mut self, archive: &Archive, vault_key: Option<&str>) -> Result<()> {
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let notebook_ids = restore_notebooks(&transaction, &archive.notebooks)?;
+        for note in &archive.notes {
+            let notebook_id = note
+                .notebook_id
+                .and_then(|id| notebook_ids.get(&id).copied());
+            remove_note(&transaction, note.id)?;
+            insert_archived(&transaction, Some(note.id), note, notebook_id)?;
+        }
+        transaction.execute("DELETE FROM vault_key", ())?;
+        if let Some(key) = vault_key {
+            transaction.execute("INSERT INTO vault_key (id, key) VALUES (1, ?1)", [key])?;
+        }
+        transaction.commit()
+    }
+
+    /// writes what an unlocked encrypted vault changed, in one transaction. `replaced` notes are
+    /// written over the stored ones with their whole history, `changed` ones are updated in
+    /// place or inserted and get the revisions they carry appended, `removed` ones are deleted
+    pub fn write_sealed(
+        &mut self,
+        notebooks: &[ArchiveNotebook],
+        replaced: &[ArchiveNote],
+        changed: &[ArchiveNote],
+        removed: &[usize],
+    ) -> Result<()> {
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let notebook_ids = restore_notebooks(&transaction, notebooks)?;
+        let local = |id: Option<usize>| id.and_then(|id| notebook_ids.get(&id).copied());
+        for note in replaced {
+            remove_note(&transaction, note.id)?;
+            insert_archived(&transaction, Some(note.id), note, local(note.notebook_id))?;
+        }
+        for note in changed {
+            write_archived(&transaction, note, local(note.notebook_id))?;
+        }
+        for &id in removed {
+            remove_note(&transaction, id)?;
+        }
+        transaction.commit()
+    }
+
+    /// the salt and check of an encrypted vault, `None` when it isn't encrypted
+    pub fn get_vault_key(&self) -> Result<Option<String>> {
+        self.db
+            .query_row("SELECT key FROM vault_key WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .optional()
+    }
+
+    pub fn add_notebook(&self, name: &str, parent_id: Option<usize>) -> Result<usize> {
+        self.db.execute(
+            "INSERT INTO notebook (name, parent_id) VALUES (?1, ?2)",
+            params![name, parent_id],
+        )?;
+        Ok(self.db.last_insert_rowid() as usize)
+    }
+
+    pub fn get_notebook(&self, notebook_id: usize) -> Result<Option<Notebook>> {
+        self.db
+            .query_row(
+                "SELECT id, name, parent_id FROM notebook WHERE id = ?1",
+                [notebook_id],
+                notebook_from_row,
+            )
+            .optional()
+    }
+
+    pub fn get_notebooks(&self) -> Result<Vec<Notebook>> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, name, parent_id FROM notebook ORDER BY name")?;
+        let notebooks = stmt.query_map([], notebook_from_row)?;
+        notebooks.collect()
+    }
+}
+
+fn note_from_row(row: &Row) -> Result<Note> {
+    let mut note = Note::new(row.get(0)?, row.get(1)?, row.get(2)?);
+    note.notebook_id = row.get(3)?;
+    note.deleted_at = row.get(4)?;
+    note.created_at = row.get(5)?;
+    note.updated_at = row.get(6)?;
+    note.pinned = row.get(7)?;
+    let tags: Option<String> = row.get(8)?;
+    note.tags = tags
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    note.tags.sort();
+    Ok(note)
+}
+
+/// finds or creates every archived notebook, parents first. returns the local id of each
+/// archived id, which is kept when it is free
+fn restore_notebooks(
+    db: &Connection,
+    notebooks: &[ArchiveNotebook],
+) -> Result<HashMap<usize, usize>> {
+    let mut ids = HashMap::new();
+    let mut waiting: Vec<&ArchiveNotebook> = notebooks.iter().collect();
+    while !waiting.is_empty() {
+        let (mut ready, rest): (Vec<_>, Vec<_>) = waiting
+            .into_iter()
+            .partition(|notebook| notebook.parent_id.is_none_or(|id| ids.contains_key(&id)));
+        waiting = rest;
+        if ready.is_empty() {
+            // their parents aren't in the archive, they go to the top level
+            ready = std::mem::take(&mut waiting);
+        }
+        for notebook in ready {
+            let parent = notebook.parent_id.and_then(|id| ids.get(&id).copied());
+            let existing: Option<usize> = db
+                .query_row(
+                    "SELECT id FROM notebook WHERE name = ?1 AND parent_id IS ?2 ORDER BY id",
+                    params![notebook.name, parent],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let id = match existing {
+                Some(id) => id,
+                None => {
+                    db.execute(
+                        "INSERT INTO notebook (id, name, parent_id) VALUES (
+                            (SELECT ?1 WHERE NOT EXISTS (SELECT 1 FROM notebook WHERE id = ?1)),
+                            ?2, ?3)",
+                        params![notebook.id, notebook.name, parent],
+                    )?;
+                    db.last_insert_rowid() as usize
+                }
+            };
+            ids.insert(notebook.id, id);
+        }
+    }
+    Ok(ids)
+}
+
+/// stores an archived note as it was, `None` gives it a new id
+fn insert_archived(
+    db: &Connection,
+    id: Option<usize>,
+    note: &ArchiveNote,
+    notebook_id: Option<usize>,
+) -> Result<usize> {
+    db.execute(
+        "INSERT INTO note (id, title, text, notebook_id, pinned, created_at, updated_at, deleted_at)
+        VALUES (?1, ?2, ?3, ?4, ?5,
+            COALESCE(?6, datetime('now')), COALESCE(?7, ?6, datetime('now')), ?8)",
+        params![
+            id,
+            note.title,
+            note.text,
+            notebook_id,
+            note.pinned,
+            note.created_at,
+            note.updated_at,
+            note.deleted_at
+        ],
+    )?;
+    let id = db.last_insert_rowid() as usize;
+    write_links(db, id, &note.text)?;
+    write_tags(db, id, &note.tags)?;
+    resolve_links(db, id, None)?;
+    if note.revisions.is_empty() {
+        let current = Note::new(Some(id), note.title.clone(), note.text.clone());
+        write_revision(db, id, &current)?;
+        return Ok(id);
+    }
+    insert_revisions(db, id, &note.revisions)?;
+    Ok(id)
+}
+
+/// writes an archived note over the stored one with its id, or inserts it. its revisions are
+/// added to the stored ones
+fn write_archived(db: &Connection, note: &ArchiveNote, notebook_id: Option<usize>) -> Result<()> {
+    let old_title: Option<String> = db
+        .query_row("SELECT title FROM note WHERE id = ?1", [note.id], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    if old_title.is_some() {
+        db.execute(
+            "UPDATE note SET title = ?1, text = ?2, notebook_id = ?3, pinned = ?4,
+                created_at = COALESCE(?5, created_at), updated_at = COALESCE(?6, updated_at),
+                deleted_at = ?7
+            WHERE id = ?8",
+            params![
+                note.title,
+                note.text,
+                notebook_id,
+                note.pinned,
+                note.created_at,
+                note.updated_at,
+                note.deleted_at,
+                note.id
+            ],
+        )?;
+    } else {
+        db.execute(
+            "INSERT INTO note (id, title, text, notebook_id, pinned, created_at, updated_at, deleted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5,
+                COALESCE(?6, datetime('now')), COALESCE(?7, ?6, datetime('now')), ?8)",
+            params![
+                note.id,
+                note.title,
+                note.text,
+                notebook_id,
+                note.pinned,
+                note.created_at,
+                note.updated_at,
+                note.deleted_at
+            ],
+        )?;
+    }
+    write_links(db, note.id, &note.text)?;
+    write_tags(db, note.id, &note.tags)?;
+    resolve_links(db, note.id, old_title.as_deref())?;
+    insert_revisions(db, note.id, &note.revisions)
+}
+
+fn insert_revisions(db: &Connection, note_id: usize, revisions: &[ArchiveRevision]) -> Result<()> {
+    let mut stmt = db.prepare(
+        "INSERT INTO note_revision (note_id, revision, title, text, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for revision in revisions {
+        stmt.execute(params![
+            note_id,
+            revision.revision,
+            revision.title,
+            revision.text,
+            revision.created_at
+        ])?;
+    }
+    Ok(())
 }
 
-#[derive(Clone, Debug)]
+/// deletes the note with its links, tags and revisions, the links naming it break
+fn remove_note(db: &Connection, note_id: usize) -> Result<()> {
+    let title: Option<String> = db
+        .query_row("SELECT title FROM note WHERE id = ?1", [note_id], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    db.execute("DELETE FROM note WHERE id = ?1", [note_id])?;
+    resolve_links(db, note_id, title.as_deref())
+}
+
+/// re-points the links a write of the note could have changed, see `RESOLVE_LINKS`
+fn resolve_links(db: &Connection, note_id: usize, old_title: Option<&str>) -> Result<()> {
+    db.execute(RESOLVE_LINKS, params![note_id, old_title])?;
+    Ok(())
+}
+
+fn link_from_row(row: &Row) -> Result<NoteLink> {
+    Ok(NoteLink {
+        source_id: row.get(0)?,
+        target: row.get(1)?,
+        target_id: row.get(2)?,
+    })
+}
+
+/// inserts the note with its id, or the one sqlite picks when it has none
+fn insert_note(db: &Connection, note: &Note) -> Result<usize> {
+    db.execute(
+        "INSERT INTO note (id, title, text, notebook_id, created_at, updated_at, pinned)
+        VALUES (?1, ?2, ?3, ?4, COALESCE(?5, datetime('now')), COALESCE(?6, ?5, datetime('now')), ?7)",
+        params![
+            note.id,
+            note.title,
+            note.text,
+            note.notebook_id,
+            note.created_at,
+            note.updated_at,
+            note.pinned
+        ],
+    )?;
+    let id = db.last_insert_rowid() as usize;
+    record_note(db, id, note)?;
+    resolve_links(db, id, None)?;
+    Ok(id)
+}
+
+/// the notes with the ids, in their order
+fn read_notes(db: &Connection, ids: &[usize]) -> Result<Vec<Note>> {
+    let mut stmt = db.prepare(&format!("SELECT {NOTE_COLUMNS} FROM note WHERE id = ?1"))?;
+    ids.iter()
+        .map(|id| stmt.query_row([id], note_from_row))
+        .collect()
+}
+
+fn update_note(db: &Connection, note_id: usize, note: &Note) -> Result<()> {
+    let old_title: Option<String> = db
+        .query_row("SELECT title FROM note WHERE id = ?1", [note_id], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    let updated = db.execute(
+        // only a new title or text counts as an edit
+        "UPDATE note SET
+            updated_at = CASE WHEN title = ?1 AND text = ?2 THEN updated_at ELSE datetime('now') END,
+            title = ?1, text = ?2, notebook_id = ?3, pinned = ?4
+        WHERE id = ?5",
+        params![note.title, note.text, note.notebook_id, note.pinned, note_id],
+    )?;
+    if updated == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    record_note(db, note_id, note)?;
+    // a new title breaks or fixes links by title
+    resolve_links(db, note_id, old_title.as_deref())
+}
+
+/// keeps the links, tags and revisions of a note that was just written in sync with it
+fn record_note(db: &Connection, note_id: usize, note: &Note) -> Result<()> {
+    write_links(db, note_id, &note.text)?;
+    write_tags(db, note_id, &note.tags)?;
+    write_revision(db, note_id, note)
+}
+
+fn write_tags(db: &Connection, note_id: usize, tags: &[String]) -> Result<()> {
+    db.execute("DELETE FROM note_tag WHERE note_id = ?1", [note_id])?;
+    let mut stmt = db.prepare("INSERT OR IGNORE INTO note_tag (note_id, tag) VALUES (?1, ?2)")?;
+    for tag in tags.iter().filter_map(|tag| normalize_tag(tag)) {
+        stmt.execute(params![note_id, tag])?;
+    }
+    Ok(())
+}
+
+/// microseconds since the unix epoch as `YYYY-MM-DD HH:MM:SS`, the way the database keeps times
+pub(crate) fn utc_time(usec: i64) -> String {
+    let secs = usec.div_euclid(1_000_000);
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    // the civil date of a day count, from howard hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+/// `#Work Stuff` is stored as `Work-Stuff`, a tag of only `#` or whitespace is dropped
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#');
+    let tag: Vec<&str> = tag.split_whitespace().collect();
+    (!tag.is_empty()).then(|| tag.join("-"))
+}
+
+/// appends the note as its newest revision, unless its title and text didn't change
+fn write_revision(db: &Connection, note_id: usize, note: &Note) -> Result<()> {
+    let latest: Option<(usize, String, String)> = db
+        .query_row(
+            "SELECT revision, title, text FROM note_revision
+            WHERE note_id = ?1 ORDER BY revision DESC LIMIT 1",
+            [note_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let revision = match latest {
+        Some((_, title, text)) if title == note.title && text == note.text => return Ok(()),
+        Some((revision, _, _)) => revision + 1,
+        None => 1,
+    };
+    db.execute(
+        "INSERT INTO note_revision (note_id, revision, title, text) VALUES (?1, ?2, ?3, ?4)",
+        params![note_id, revision, note.title, note.text],
+    )?;
+    Ok(())
+}
+
+/// replaces the links of the note with the ones written in its text, they still need resolving
+fn write_links(db: &Connection, note_id: usize, text: &str) -> Result<()> {
+    db.execute("DELETE FROM note_link WHERE source_id = ?1", [note_id])?;
+    let mut stmt = db.prepare("INSERT INTO note_link (source_id, target) VALUES (?1, ?2)")?;
+    for target in parse_links(text) {
+        stmt.execute(params![note_id, target])?;
+    }
+    Ok(())
+}
+
+fn revision_from_row(row: &Row) -> Result<Revision> {
+    Ok(Revision {
+        note_id: row.get(0)?,
+        revision: row.get(1)?,
+        title: row.get(2)?,
+        text: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn notebook_from_row(row: &Row) -> Result<Notebook> {
+    Ok(Notebook {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Note {
     pub id: Option<usize>,
     pub title: String,
     pub text: String,
+    pub notebook_id: Option<usize>,
+    /// when the note was moved to the trash, utc time as `YYYY-MM-DD HH:MM:SS`
+    pub deleted_at: Option<String>,
+    /// set by the repository when the note is stored, utc time as `YYYY-MM-DD HH:MM:SS`
+    pub created_at: Option<String>,
+    /// last time the title or text changed, utc time as `YYYY-MM-DD HH:MM:SS`
+    pub updated_at: Option<String>,
+    /// pinned notes are listed before the others
+    pub pinned: bool,
+    /// sorted, without whitespace or a leading `#`
+    pub tags: Vec<String>,
 }
 
 impl Note {
     pub fn new(id: Option<usize>, title: String, text: String) -> Note {
-        Note { id, title, text }
+        Note {
+            id,
+            title,
+            text,
+            notebook_id: None,
+            deleted_at: None,
+            created_at: None,
+            updated_at: None,
+            pinned: false,
+            tags: Vec::new(),
+        }
     }
 }
 
+/// `[[target]]` written in the text of `source_id`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoteLink {
+    pub source_id: usize,
+    pub target: String,
+    /// the note the link resolves to, `None` if it is broken
+    pub target_id: Option<usize>,
+}
+
+/// the title and text a note had after one of its updates, revisions count up from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Revision {
+    pub note_id: usize,
+    pub revision: usize,
+    pub title: String,
+    pub text: String,
+    /// utc time as `YYYY-MM-DD HH:MM:SS`
+    pub created_at: String,
+}
+
+impl Revision {
+    /// the revision in the same shape the editor shows a note
+    pub fn content(&self) -> String {
+        format!("{}\n{}", self.title, self.text)
+    }
+}
+
+/// folder of notes, notebooks nest through `parent_id`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notebook {
+    pub id: usize,
+    pub name: String,
+    pub parent_id: Option<usize>,
+}
+
 impl From<&Note> for String {
     fn from(value: &Note) -> Self {
         format!("title: {}\n{}", value.title, value.text)
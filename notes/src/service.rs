@@ -1,55 +1,293 @@
-use std::{cmp::Reverse, collections::HashMap};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::HashSet,
+    fmt::Display,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 
-use crate::repository::{Note, Repository};
+use crate::{
+    ai_embedding::AiClient,
+    archive::{
+        Archive, ArchiveLink, ArchiveNote, ArchiveNotebook, ArchiveRevision, Conflict,
+        ImportReport, ARCHIVE_VERSION,
+    },
+    config::Config,
+    encryption::{is_encrypted, EncryptedStore},
+    file_store::FileStore,
+    git_store::{GitStore, SyncReport},
+    links::rewrite_links,
+    pool::RepositoryPool,
+    repository::{Note, NoteLink, Notebook, Revision},
+    store::{NoteStore, StoreKind},
+};
 
+/// days a note stays in the trash before `purge_expired` deletes it for good
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// smallest score of a semantic search result unless the config sets another
+pub const DEFAULT_SEMANTIC_THRESHOLD: f64 = 0.3;
+
+/// notes written per transaction by `import_notes`
+const IMPORT_BATCH: usize = 500;
+
+/// the notes of a store. the async methods query it on the blocking threads of tokio, the
+/// others block: call them outside the runtime or from `spawn_blocking`
 pub struct NoteService {
-    db_manager: Repository,
+    store: Arc<dyn NoteStore>,
     matcher: Matcher,
+    trash_retention_days: u32,
+    ai: AiClient,
+    /// smallest score of a semantic search result
+    semantic_threshold: f64,
+    /// the notes of an encrypted vault never go to the ai engine
+    encrypted: bool,
 }
 
 impl NoteService {
-    pub fn new(db_manager: Repository) -> NoteService {
+    pub fn new(store: impl NoteStore + 'static) -> NoteService {
         NoteService {
-            db_manager,
+            store: Arc::new(store),
             matcher: Matcher::default(),
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            ai: AiClient::default(),
+            semantic_threshold: DEFAULT_SEMANTIC_THRESHOLD,
+            encrypted: false,
+        }
+    }
+
+    /// the store of the config with its settings, created when it doesn't exist yet. an
+    /// encrypted vault needs `open_with` and its passphrase
+    pub fn open(config: &Config) -> anyhow::Result<NoteService> {
+        NoteService::open_with(config, None)
+    }
+
+    /// like `open`, unlocking an encrypted vault with the passphrase
+    pub fn open_with(config: &Config, passphrase: Option<&str>) -> anyhow::Result<NoteService> {
+        let service = match config.store {
+            StoreKind::Sqlite => {
+                let pool = RepositoryPool::open(&config.db)?;
+                match (is_encrypted(&pool)?, passphrase) {
+                    (false, _) => NoteService::new(pool),
+                    (true, Some(passphrase)) => NoteService {
+                        encrypted: true,
+                        ..NoteService::new(EncryptedStore::unlock(pool, passphrase)?)
+                    },
+                    (true, None) => bail!("the vault is encrypted, it needs the passphrase"),
+                }
+            }
+            StoreKind::Markdown => NoteService::new(FileStore::open(&config.db)?),
+            StoreKind::Git => NoteService::new(GitStore::open(
+                &config.db,
+                Duration::from_secs(config.commit_interval),
+            )?),
+        };
+        Ok(service.set_config(config))
+    }
+
+    /// whether the vault of the config is encrypted and opens with `open_with`
+    pub fn needs_passphrase(config: &Config) -> anyhow::Result<bool> {
+        match config.store {
+            StoreKind::Sqlite => is_encrypted(&RepositoryPool::open(&config.db)?),
+            StoreKind::Markdown | StoreKind::Git => Ok(false),
+        }
+    }
+
+    /// the store for the blocking methods, async ones go through `run`
+    fn db(&self) -> &dyn NoteStore {
+        self.store.as_ref()
+    }
+
+    /// runs `work` with the store on a blocking thread of tokio
+    async fn run<T, F>(&self, work: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn NoteStore) -> anyhow::Result<T> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || work(store.as_ref()))
+            .await
+            .context("store task")?
+    }
+
+    /// takes the trash retention, the ai engine and the semantic threshold of the config. an
+    /// encrypted vault keeps no ai engine, it would get the notes in plain text
+    pub fn set_config(self, config: &Config) -> Self {
+        let ai_engine = config.ai_engine.clone().filter(|_| !self.encrypted);
+        NoteService {
+            trash_retention_days: config.trash_days,
+            ai: AiClient::new(ai_engine),
+            semantic_threshold: config.semantic_threshold,
+            ..self
+        }
+    }
+
+    pub fn set_trash_retention(self, days: u32) -> Self {
+        NoteService {
+            trash_retention_days: days,
+            ..self
         }
     }
 
-    pub async fn add_note(&mut self, note: Note) -> anyhow::Result<()> {
-        let notes = vec![note];
-        self.db_manager.add(&notes).context("service add note")?;
-        crate::add(&notes).await.context("service ai add note")
+    /// stores a new note and indexes it in the ai engine, returns it as stored
+    pub async fn add_note(&mut self, note: Note) -> anyhow::Result<Note> {
+        let notes = self
+            .run(move |db| db.add(&[note]))
+            .await
+            .context("service add note")?;
+        if self.ai.is_configured() {
+            self.ai.add(&notes).await.context("service ai add note")?;
+        }
+        notes.into_iter().next().context("service add note")
     }
 
-    pub async fn search_ai(&self, query: &str) -> anyhow::Result<Vec<(Note, f64)>> {
-        let resp = crate::search(query).await?;
-        let res = resp
+    /// stores the note without indexing it in the ai engine, notes with an id are updated.
+    /// returns the id of the stored note
+    ///
+    /// renaming a note rewrites the `[[Old Title]]` links to it in the same transaction
+    pub fn save_note(&mut self, mut note: Note) -> anyhow::Result<usize> {
+        let Some(id) = note.id else {
+            return self.db().insert(&note).context("service insert note");
+        };
+        let old = self.get_note(id)?;
+        let mut notes = self.rewritten_backlinks(&old, &note.title)?;
+        // the note can link to itself
+        notes.retain(|linking| linking.id != Some(id));
+        if old.title.trim() != note.title.trim() {
+            if let Some(text) = rewrite_links(&note.text, old.title.trim(), note.title.trim()) {
+                note.text = text;
+            }
+        }
+        notes.insert(0, note);
+        self.db()
+            .update_all(&notes)
+            .context("service update note")?;
+        Ok(id)
+    }
+
+    /// notes that would have their links rewritten if the note was renamed to `new_title`,
+    /// with the rewritten text. nothing is saved
+    pub fn preview_rename(&self, id: usize, new_title: &str) -> anyhow::Result<Vec<Note>> {
+        let note = self.get_note(id)?;
+        self.rewritten_backlinks(&note, new_title)
+    }
+
+    fn rewritten_backlinks(&self, note: &Note, new_title: &str) -> anyhow::Result<Vec<Note>> {
+        let (old_title, new_title) = (note.title.trim(), new_title.trim());
+        if old_title == new_title {
+            return Ok(Vec::new());
+        }
+        let backlinks = self.get_backlinks(note.id.context("note without id")?)?;
+        let notes = backlinks
             .into_iter()
-            .map(|(key, value)| (self.get_note(key).unwrap(), value))
-            .filter(|&(_, score)| score > 0.3)
+            .filter_map(|mut linking| {
+                linking.text = rewrite_links(&linking.text, old_title, new_title)?;
+                Some(linking)
+            })
             .collect();
+        Ok(notes)
+    }
+
+    pub async fn search_ai(
+        &self,
+        query: &str,
+        notebook: Option<usize>,
+        order: SortOrder,
+    ) -> anyhow::Result<Vec<(Note, f64)>> {
+        if self.encrypted {
+            bail!("semantic search is off in encrypted vaults");
+        }
+        let resp = self.ai.search(query).await?;
+        // the engine can still know notes that were purged since, only stored ones are kept
+        let (notebooks, notes) = self
+            .run(|db| Ok((db.get_notebooks()?, db.get_notes()?)))
+            .await
+            .context("service ai search")?;
+        let scope = scope_of(&notebooks, notebook);
+        let mut res: Vec<(Note, f64)> = notes
+            .into_iter()
+            .filter_map(|note| {
+                let score = *resp.get(&note.id?)?;
+                Some((note, score))
+            })
+            .filter(|&(_, score)| score > self.semantic_threshold)
+            .filter(|(note, _)| in_scope(&scope, note))
+            .collect();
+        res.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        res.sort_by(|(a, _), (b, _)| order.compare(a, b));
         Ok(res)
     }
 
     pub fn get_note(&self, id: usize) -> anyhow::Result<Note> {
-        self.db_manager.get_note(id).context("service get one note")
+        self.db().get_note(id).context("service get one note")
     }
 
     pub fn get_all(&self) -> anyhow::Result<Vec<Note>> {
-        self.db_manager.get_notes().context("service get all notes")
+        self.db().get_notes().context("service get all notes")
     }
 
+    /// moves the note to the trash
     pub fn delete_note(&mut self, id: usize) -> anyhow::Result<Note> {
-        self.db_manager.delete(id).context("service delete note")
+        self.db().delete(id).context("service delete note")
+    }
+
+    pub fn get_trash(&self) -> anyhow::Result<Vec<Note>> {
+        self.db().get_trash().context("service get trash")
+    }
+
+    /// takes the note out of the trash
+    pub fn restore_note(&mut self, id: usize) -> anyhow::Result<()> {
+        self.db()
+            .restore(id)
+            .with_context(|| format!("note {id} isn't in the trash"))
+    }
+
+    /// deletes a note in the trash for good
+    pub fn purge_note(&mut self, id: usize) -> anyhow::Result<()> {
+        self.db()
+            .purge(id)
+            .with_context(|| format!("note {id} isn't in the trash"))
     }
 
-    pub fn search_notes(&self, query: &str) -> anyhow::Result<Vec<(Note, usize)>> {
-        let notes = self.get_all()?;
+    /// purges every note in the trash, returns how many
+    pub fn empty_trash(&mut self) -> anyhow::Result<usize> {
+        self.db().empty_trash().context("service empty trash")
+    }
+
+    /// purges notes that stayed in the trash longer than the retention, returns how many
+    pub fn purge_expired(&mut self) -> anyhow::Result<usize> {
+        self.db()
+            .purge_older_than(self.trash_retention_days)
+            .context("service purge expired notes")
+    }
+
+    /// notes inside the notebook or any of its sub notebooks, all notes for `None`
+    pub fn get_notes_in(
+        &self,
+        notebook: Option<usize>,
+        order: SortOrder,
+    ) -> anyhow::Result<Vec<Note>> {
+        let scope = self.notebook_scope(notebook)?;
+        let mut notes = self.get_all()?;
+        notes.retain(|note| in_scope(&scope, note));
+        notes.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(order.compare(a, b)));
+        Ok(notes)
+    }
+
+    /// best matches first, unless `order` asks for another order
+    pub fn search_notes(
+        &self,
+        query: &str,
+        notebook: Option<usize>,
+        order: SortOrder,
+    ) -> anyhow::Result<Vec<(Note, usize)>> {
+        let notes = self.get_notes_in(notebook, SortOrder::Relevance)?;
         // use note refs so it can be passed from another function later, probably a cache
-        let notes_refs: Vec<&Note> = notes.iter().map(|note| note).collect();
+        let notes_refs: Vec<&Note> = notes.iter().collect();
 
         let notes_scores = self.matcher.match_all(query, &notes_refs[..]);
         let mut selected_notes: Vec<(Note, usize)> = notes
@@ -59,12 +297,314 @@ impl NoteService {
             .map(|(index, note)| (note, notes_scores[index].unwrap()))
             .collect();
         selected_notes.sort_by_key(|&(_, score)| Reverse(score));
+        selected_notes.sort_by(|(a, _), (b, _)| order.compare(a, b));
         Ok(selected_notes)
     }
 
     pub fn semantic_search_notes(&self, _query: &str) -> Vec<Note> {
         todo!()
     }
+
+    /// saved versions of the note, oldest first
+    pub fn get_history(&self, id: usize) -> anyhow::Result<Vec<Revision>> {
+        self.db()
+            .get_revisions(id)
+            .context("service get note history")
+    }
+
+    /// brings back the title and text of an old revision, which is saved as a new revision
+    pub fn restore_revision(&mut self, id: usize, revision: usize) -> anyhow::Result<()> {
+        let old = self
+            .db()
+            .get_revision(id, revision)
+            .with_context(|| format!("note {id} has no revision {revision}"))?;
+        let mut note = self.get_note(id)?;
+        note.title = old.title;
+        note.text = old.text;
+        self.save_note(note)?;
+        Ok(())
+    }
+
+    /// links written in the note
+    pub fn get_links(&self, id: usize) -> anyhow::Result<Vec<NoteLink>> {
+        self.db().get_links(id).context("service get links")
+    }
+
+    /// notes linking to the note
+    pub fn get_backlinks(&self, id: usize) -> anyhow::Result<Vec<Note>> {
+        self.db().get_backlinks(id).context("service get backlinks")
+    }
+
+    /// links to notes that were deleted, renamed or never existed
+    pub fn get_broken_links(&self) -> anyhow::Result<Vec<NoteLink>> {
+        self.db()
+            .get_broken_links()
+            .context("service get broken links")
+    }
+
+    pub fn get_notebooks(&self) -> anyhow::Result<Vec<Notebook>> {
+        self.db().get_notebooks().context("service get notebooks")
+    }
+
+    pub fn create_notebook(&self, name: &str, parent: Option<usize>) -> anyhow::Result<usize> {
+        let name = name.trim();
+        if name.is_empty() || name.contains('/') {
+            bail!("invalid notebook name {name:?}");
+        }
+        if let Some(parent) = parent {
+            self.get_notebook(parent)?;
+        }
+        self.db()
+            .add_notebook(name, parent)
+            .context("service create notebook")
+    }
+
+    pub fn get_notebook(&self, id: usize) -> anyhow::Result<Notebook> {
+        self.db()
+            .get_notebook(id)
+            .context("service get notebook")?
+            .with_context(|| format!("notebook {id} doesn't exist"))
+    }
+
+    /// finds a notebook by its path of names, like `work/projects`
+    pub fn find_notebook(&self, path: &str) -> anyhow::Result<Notebook> {
+        let notebooks = self.get_notebooks()?;
+        let mut parent = None;
+        let mut found = None;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let notebook = notebooks
+                .iter()
+                .find(|notebook| notebook.parent_id == parent && notebook.name == name)
+                .with_context(|| format!("notebook {path:?} doesn't exist"))?;
+            parent = Some(notebook.id);
+            found = Some(notebook);
+        }
+        found
+            .cloned()
+            .with_context(|| format!("notebook {path:?} doesn't exist"))
+    }
+
+    pub fn pin_note(&self, id: usize, pinned: bool) -> anyhow::Result<()> {
+        self.db()
+            .set_pinned(id, pinned)
+            .with_context(|| format!("service pin note {id}"))
+    }
+
+    /// adds the notes of an import a batch at a time, without indexing them in the ai engine.
+    /// a failed batch leaves the batches before it in place
+    pub fn import_notes(&mut self, notes: &[Note]) -> anyhow::Result<()> {
+        for batch in notes.chunks(IMPORT_BATCH) {
+            self.db().add(batch).context("service import notes")?;
+        }
+        Ok(())
+    }
+
+    /// updates the notes of an import whose id is stored and adds the others, in one
+    /// transaction. returns them as stored
+    pub fn upsert_notes(&mut self, notes: &[Note]) -> anyhow::Result<Vec<Note>> {
+        self.db().upsert(notes).context("service upsert notes")
+    }
+
+    /// everything in the store, the trash included
+    pub fn export_archive(&self) -> anyhow::Result<Archive> {
+        let notebooks = self
+            .get_notebooks()?
+            .into_iter()
+            .map(|notebook| ArchiveNotebook {
+                id: notebook.id,
+                name: notebook.name,
+                parent_id: notebook.parent_id,
+            })
+            .collect();
+        let mut notes = self.get_all()?;
+        notes.extend(self.get_trash()?);
+        notes.sort_by_key(|note| note.id);
+        let notes = notes
+            .into_iter()
+            .map(|note| {
+                let id = note.id.context("stored note without id")?;
+                let links = self
+                    .get_links(id)?
+                    .into_iter()
+                    .map(|link| ArchiveLink {
+                        target: link.target,
+                        target_id: link.target_id,
+                    })
+                    .collect();
+                let revisions = self
+                    .get_history(id)?
+                    .into_iter()
+                    .map(|revision| ArchiveRevision {
+                        revision: revision.revision,
+                        title: revision.title,
+                        text: revision.text,
+                        created_at: revision.created_at,
+                    })
+                    .collect();
+                Ok(ArchiveNote {
+                    id,
+                    title: note.title,
+                    text: note.text,
+                    notebook_id: note.notebook_id,
+                    pinned: note.pinned,
+                    tags: note.tags,
+                    created_at: note.created_at,
+                    updated_at: note.updated_at,
+                    deleted_at: note.deleted_at,
+                    links,
+                    revisions,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Archive {
+            version: ARCHIVE_VERSION,
+            ai_engine: self.ai.url().map(str::to_string),
+            notebooks,
+            notes,
+        })
+    }
+
+    /// restores an archive into this store, nothing is written if any part of it fails
+    pub fn import_archive(
+        &mut self,
+        archive: &Archive,
+        conflict: Conflict,
+    ) -> anyhow::Result<ImportReport> {
+        self.db()
+            .restore_archive(archive, conflict)
+            .context("service import archive")
+    }
+
+    /// replaces the tags of the note
+    pub fn set_tags(&mut self, id: usize, tags: Vec<String>) -> anyhow::Result<()> {
+        let mut note = self.get_note(id)?;
+        note.tags = tags;
+        self.db()
+            .update(id, note)
+            .with_context(|| format!("service tag note {id}"))
+    }
+
+    /// moves the note into the notebook, `None` moves it out of every notebook
+    pub fn move_note(&mut self, id: usize, notebook: Option<usize>) -> anyhow::Result<()> {
+        if let Some(notebook) = notebook {
+            self.get_notebook(notebook)?;
+        }
+        self.db()
+            .move_note(id, notebook)
+            .with_context(|| format!("service move note {id}"))
+    }
+
+    /// commits the notes waiting for it and pulls and pushes commits with the remote, the
+    /// store has to be kept in git
+    pub fn sync(&self, remote: &str) -> anyhow::Result<SyncReport> {
+        self.db()
+            .sync(remote)
+            .with_context(|| format!("sync notes with {remote}"))
+    }
+
+    /// the notebook and all of its descendants, `None` when everything is in scope
+    fn notebook_scope(&self, notebook: Option<usize>) -> anyhow::Result<Option<HashSet<usize>>> {
+        if notebook.is_none() {
+            return Ok(None);
+        }
+        Ok(scope_of(&self.get_notebooks()?, notebook))
+    }
+}
+
+/// the notebook and all of its descendants among `notebooks`, `None` for everything
+fn scope_of(notebooks: &[Notebook], notebook: Option<usize>) -> Option<HashSet<usize>> {
+    let root = notebook?;
+    let mut scope = HashSet::from([root]);
+    let mut added = true;
+    while added {
+        added = false;
+        for notebook in notebooks {
+            if notebook
+                .parent_id
+                .is_some_and(|parent| scope.contains(&parent))
+                && scope.insert(notebook.id)
+            {
+                added = true;
+            }
+        }
+    }
+    Some(scope)
+}
+
+fn in_scope(scope: &Option<HashSet<usize>>, note: &Note) -> bool {
+    match (scope, note.notebook_id) {
+        (None, _) => true,
+        (Some(scope), Some(notebook)) => scope.contains(&notebook),
+        (Some(_), None) => false,
+    }
+}
+
+/// orders notes are listed in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// most recently edited first
+    #[default]
+    Updated,
+    /// most recently created first
+    Created,
+    /// title from A to Z
+    Title,
+    /// best match for a search first, callers rank by their own scores
+    Relevance,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 4] = [
+        SortOrder::Updated,
+        SortOrder::Created,
+        SortOrder::Title,
+        SortOrder::Relevance,
+    ];
+
+    /// the order after this one, wraps around
+    pub fn next(self) -> SortOrder {
+        let index = SortOrder::ALL.iter().position(|&order| order == self);
+        SortOrder::ALL[index.map_or(0, |index| (index + 1) % SortOrder::ALL.len())]
+    }
+
+    /// compares two notes, `Relevance` keeps them as they are
+    pub fn compare(self, a: &Note, b: &Note) -> Ordering {
+        match self {
+            SortOrder::Updated => b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)),
+            SortOrder::Created => b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)),
+            SortOrder::Title => a
+                .title
+                .to_lowercase()
+                .cmp(&b.title.to_lowercase())
+                .then(a.id.cmp(&b.id)),
+            SortOrder::Relevance => Ordering::Equal,
+        }
+    }
+}
+
+impl Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SortOrder::Updated => "updated",
+            SortOrder::Created => "created",
+            SortOrder::Title => "title",
+            SortOrder::Relevance => "relevance",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SortOrder::ALL
+            .into_iter()
+            .find(|order| order.to_string() == s)
+            .ok_or_else(|| {
+                format!("unknown sort order {s:?}, use updated, created, title or relevance")
+            })
+    }
 }
 
 #[derive(Default)]
@@ -88,3 +628,297 @@ impl Matcher {
         result.map(|sim| sim.max(0) as usize)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{NoteService, SortOrder};
+    use crate::{
+        config::Config, encryption::encrypt_vault, pool::RepositoryPool, repository::Note,
+    };
+
+    #[test]
+    fn notebook_scope_includes_sub_notebooks() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let work = service.create_notebook("work", None).unwrap();
+        let projects = service.create_notebook("projects", Some(work)).unwrap();
+        let home = service.create_notebook("home", None).unwrap();
+        for (title, notebook) in [("a", Some(projects)), ("b", Some(home)), ("c", None)] {
+            let mut note = Note::new(None, title.to_string(), String::new());
+            note.notebook_id = notebook;
+            service.save_note(note).unwrap();
+        }
+
+        let found = service.find_notebook("work/projects").unwrap();
+        assert_eq!(found.id, projects);
+        let titles: Vec<_> = service
+            .get_notes_in(Some(work), SortOrder::Title)
+            .unwrap()
+            .into_iter()
+            .map(|note| note.title)
+            .collect();
+        assert_eq!(titles, ["a"]);
+
+        service.move_note(3, Some(work)).unwrap();
+        assert_eq!(
+            service
+                .get_notes_in(Some(work), SortOrder::Updated)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(service.move_note(3, Some(100)).is_err());
+    }
+
+    #[tokio::test]
+    async fn new_notes_get_ids() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let first = service
+            .add_note(Note::new(None, "a".to_string(), "[[b]]".to_string()))
+            .await
+            .unwrap();
+        let second = service
+            .add_note(Note::new(None, "b".to_string(), String::new()))
+            .await
+            .unwrap();
+        assert_eq!((first.id, second.id), (Some(1), Some(2)));
+        assert!(first.created_at.is_some());
+        assert_eq!(service.get_links(1).unwrap()[0].target_id, Some(2));
+
+        // stored ids are updated, unknown ones kept and missing ones picked
+        let stored = service
+            .upsert_notes(&[
+                Note::new(Some(2), "b".to_string(), "changed".to_string()),
+                Note::new(Some(7), "c".to_string(), String::new()),
+                Note::new(None, "d".to_string(), String::new()),
+            ])
+            .unwrap();
+        let ids: Vec<_> = stored.iter().map(|note| note.id).collect();
+        assert_eq!(ids, [Some(2), Some(7), Some(8)]);
+        assert_eq!(service.get_note(2).unwrap().text, "changed");
+        assert_eq!(service.get_all().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn database_created_on_first_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join("notes").join("notes.db");
+        let mut service = NoteService::new(RepositoryPool::open(&path).unwrap());
+        assert!(service.get_all().unwrap().is_empty());
+        service
+            .save_note(Note::new(None, "kept".to_string(), String::new()))
+            .unwrap();
+        drop(service);
+        let service = NoteService::new(RepositoryPool::open(&path).unwrap());
+        assert_eq!(service.get_all().unwrap()[0].title, "kept");
+    }
+
+    #[test]
+    fn links_break_on_delete() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let target = service
+            .save_note(Note::new(None, "Target".to_string(), String::new()))
+            .unwrap();
+        let text = format!("[[target]] [[{target}]] [[Missing]]");
+        let source = service
+            .save_note(Note::new(None, "Source".to_string(), text))
+            .unwrap();
+        let broken = |service: &NoteService| -> Vec<String> {
+            let links = service.get_broken_links().unwrap();
+            links.into_iter().map(|link| link.target).collect()
+        };
+        assert_eq!(broken(&service), ["Missing"]);
+        assert_eq!(service.get_backlinks(target).unwrap()[0].id, Some(source));
+
+        service
+            .save_note(Note::new(None, "Missing".to_string(), String::new()))
+            .unwrap();
+        service.delete_note(target).unwrap();
+        assert_eq!(broken(&service), ["target", "1"]);
+        assert!(service.get_backlinks(target).unwrap().is_empty());
+    }
+
+    #[test]
+    fn links_follow_titles() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let source = service
+            .save_note(Note::new(
+                None,
+                "Source".to_string(),
+                "[[plan]]".to_string(),
+            ))
+            .unwrap();
+        let target = |service: &NoteService| service.get_links(source).unwrap()[0].target_id;
+        let first = service
+            .save_note(Note::new(None, "Draft".to_string(), String::new()))
+            .unwrap();
+        assert_eq!(target(&service), None);
+
+        // a note taking the title is linked, a later one with the same title isn't
+        let mut note = service.get_note(first).unwrap();
+        note.title = " Plan ".to_string();
+        service.save_note(note).unwrap();
+        assert_eq!(target(&service), Some(first));
+        let second = service
+            .save_note(Note::new(None, "PLAN".to_string(), String::new()))
+            .unwrap();
+        assert_eq!(target(&service), Some(first));
+        service.delete_note(first).unwrap();
+        assert_eq!(target(&service), Some(second));
+        service.restore_note(first).unwrap();
+        assert_eq!(target(&service), Some(first));
+    }
+
+    #[test]
+    fn trash() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let id = service
+            .save_note(Note::new(None, "Title".to_string(), String::new()))
+            .unwrap();
+        service.delete_note(id).unwrap();
+        assert!(service.get_all().unwrap().is_empty());
+        assert_eq!(service.get_trash().unwrap()[0].id, Some(id));
+        // still young enough to stay
+        assert_eq!(service.purge_expired().unwrap(), 0);
+
+        service.restore_note(id).unwrap();
+        assert!(service.get_trash().unwrap().is_empty());
+        assert!(service.purge_note(id).is_err());
+
+        service.delete_note(id).unwrap();
+        service.purge_note(id).unwrap();
+        assert!(service.get_note(id).is_err());
+        assert!(service.get_history(id).unwrap().is_empty());
+
+        for title in ["a", "b"] {
+            let id = service
+                .save_note(Note::new(None, title.to_string(), String::new()))
+                .unwrap();
+            service.delete_note(id).unwrap();
+        }
+        assert_eq!(service.empty_trash().unwrap(), 2);
+    }
+
+    #[test]
+    fn rename_rewrites_links() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let target = service
+            .save_note(Note::new(None, "Old".to_string(), "[[old]]".to_string()))
+            .unwrap();
+        let text = "[[Old|label]] [[Other]]".to_string();
+        let source = service
+            .save_note(Note::new(None, "Source".to_string(), text))
+            .unwrap();
+
+        let preview = service.preview_rename(target, "New").unwrap();
+        assert_eq!(preview.len(), 2);
+        assert_eq!(
+            service.get_note(source).unwrap().text,
+            "[[Old|label]] [[Other]]"
+        );
+
+        let mut renamed = service.get_note(target).unwrap();
+        renamed.title = "New".to_string();
+        service.save_note(renamed).unwrap();
+        assert_eq!(
+            service.get_note(source).unwrap().text,
+            "[[New|label]] [[Other]]"
+        );
+        assert_eq!(service.get_note(target).unwrap().text, "[[New]]");
+        assert_eq!(service.get_broken_links().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn history_and_restore() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        let id = service
+            .save_note(Note::new(None, "Title".to_string(), "first".to_string()))
+            .unwrap();
+        let mut note = service.get_note(id).unwrap();
+        note.text = "second".to_string();
+        service.save_note(note.clone()).unwrap();
+        // nothing changed, no new revision
+        service.save_note(note).unwrap();
+        service.move_note(id, None).unwrap();
+        assert_eq!(service.get_history(id).unwrap().len(), 2);
+
+        service.restore_revision(id, 1).unwrap();
+        let history = service.get_history(id).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[2].text, "first");
+        assert_eq!(service.get_note(id).unwrap().text, "first");
+        assert!(service.restore_revision(id, 7).is_err());
+    }
+
+    #[test]
+    fn sort_orders() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        for (title, created, updated) in [
+            ("b", "2024-01-01 00:00:00", "2024-03-01 00:00:00"),
+            ("C", "2024-02-01 00:00:00", "2024-02-01 00:00:00"),
+            ("a", "2024-03-01 00:00:00", "2024-03-01 00:00:00"),
+        ] {
+            let mut note = Note::new(None, title.to_string(), String::new());
+            note.created_at = Some(created.to_string());
+            note.updated_at = Some(updated.to_string());
+            service.save_note(note).unwrap();
+        }
+        let titles = |service: &NoteService, order| -> Vec<String> {
+            let notes = service.get_notes_in(None, order).unwrap();
+            notes.into_iter().map(|note| note.title).collect()
+        };
+        assert_eq!(titles(&service, SortOrder::Updated), ["a", "b", "C"]);
+        assert_eq!(titles(&service, SortOrder::Created), ["a", "C", "b"]);
+        assert_eq!(titles(&service, SortOrder::Title), ["a", "b", "C"]);
+        assert_eq!(titles(&service, SortOrder::Relevance), ["b", "C", "a"]);
+
+        let mut edited = service.get_note(2).unwrap();
+        edited.text = "edited".to_string();
+        service.save_note(edited).unwrap();
+        assert_eq!(titles(&service, SortOrder::Updated)[0], "C");
+        assert_eq!("title".parse(), Ok(SortOrder::Title));
+    }
+
+    #[test]
+    fn pinned_notes_first() {
+        let mut service = NoteService::new(RepositoryPool::in_memory().unwrap());
+        for title in ["a", "b", "c"] {
+            service
+                .save_note(Note::new(None, title.to_string(), String::new()))
+                .unwrap();
+        }
+        let titles = |service: &NoteService| -> Vec<String> {
+            let notes = service.get_notes_in(None, SortOrder::Title).unwrap();
+            notes.into_iter().map(|note| note.title).collect()
+        };
+        let updated = service.get_note(3).unwrap().updated_at;
+        service.pin_note(3, true).unwrap();
+        service.pin_note(2, true).unwrap();
+        assert_eq!(titles(&service), ["b", "c", "a"]);
+        // pinning isn't an edit
+        assert_eq!(service.get_note(3).unwrap().updated_at, updated);
+
+        service.pin_note(2, false).unwrap();
+        assert_eq!(titles(&service), ["c", "a", "b"]);
+        assert!(service.pin_note(9, true).is_err());
+    }
+
+    #[tokio::test]
+    async fn encrypted_vaults_skip_the_ai_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            db: dir.path().join("notes.db"),
+            // nothing listens there, a request to it would fail
+            ai_engine: Some("http://127.0.0.1:9".to_string()),
+            ..Config::default()
+        };
+        encrypt_vault(&RepositoryPool::open(&config.db).unwrap(), "hunter2").unwrap();
+        let mut service = NoteService::open_with(&config, Some("hunter2")).unwrap();
+        let note = service
+            .add_note(Note::new(None, "Secret".to_string(), String::new()))
+            .await
+            .unwrap();
+        assert_eq!(note.id, Some(1));
+        let search = service.search_ai("secret", None, SortOrder::Title).await;
+        assert!(search.is_err());
+    }
+}
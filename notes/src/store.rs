@@ -0,0 +1,213 @@
+// where notes live. the service talks to a `NoteStore` and doesn't know which one it got:
+// the sqlite pool, a `MemoryStore` that forgets everything when dropped, a `FileStore`
+// keeping every note as a markdown file in a directory or a `GitStore` that also commits the
+// files. searching ranks the listed notes in the service, so every store searches the same way
+//
+// stores are shared between threads, every method takes `&self` and a write that fails
+// leaves the store as it was
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::{
+    archive::{Archive, Conflict, ImportReport},
+    git_store::SyncReport,
+    pool::RepositoryPool,
+    repository::{Note, NoteLink, Notebook, Revision},
+};
+
+pub trait NoteStore: Send + Sync {
+    /// notes that aren't in the trash
+    fn get_notes(&self) -> anyhow::Result<Vec<Note>>;
+    /// the note, in the trash or not
+    fn get_note(&self, note_id: usize) -> anyhow::Result<Note>;
+    /// inserts the notes and returns them as stored, notes without an id get a new one
+    fn add(&self, notes: &[Note]) -> anyhow::Result<Vec<Note>>;
+    /// updates the notes whose id is stored and inserts the others, keeping the ids they carry
+    fn upsert(&self, notes: &[Note]) -> anyhow::Result<Vec<Note>>;
+    /// inserts one new note and returns its id
+    fn insert(&self, note: &Note) -> anyhow::Result<usize>;
+    /// moves the note to the trash
+    fn delete(&self, note_id: usize) -> anyhow::Result<Note>;
+    /// takes the note out of the trash
+    fn restore(&self, note_id: usize) -> anyhow::Result<()>;
+    /// notes in the trash, the most recently deleted first
+    fn get_trash(&self) -> anyhow::Result<Vec<Note>>;
+    /// deletes a note in the trash for good
+    fn purge(&self, note_id: usize) -> anyhow::Result<()>;
+    /// purges every note in the trash, returns how many
+    fn empty_trash(&self) -> anyhow::Result<usize>;
+    /// purges the notes that are in the trash for more than `days`, returns how many
+    fn purge_older_than(&self, days: u32) -> anyhow::Result<usize>;
+    fn update(&self, note_id: usize, note: Note) -> anyhow::Result<()>;
+    /// updates all the notes or none of them, every note needs an id
+    fn update_all(&self, notes: &[Note]) -> anyhow::Result<()>;
+    /// links written in the note, broken ones have no `target_id`
+    fn get_links(&self, note_id: usize) -> anyhow::Result<Vec<NoteLink>>;
+    /// notes that link to the note, by title
+    fn get_backlinks(&self, note_id: usize) -> anyhow::Result<Vec<Note>>;
+    /// every saved version of the note, oldest first
+    fn get_revisions(&self, note_id: usize) -> anyhow::Result<Vec<Revision>>;
+    fn get_revision(&self, note_id: usize, revision: usize) -> anyhow::Result<Revision>;
+    /// links of the notes outside the trash that point to no note
+    fn get_broken_links(&self) -> anyhow::Result<Vec<NoteLink>>;
+    /// puts the note in the notebook, `None` moves it to the top level
+    fn move_note(&self, note_id: usize, notebook_id: Option<usize>) -> anyhow::Result<()>;
+    /// pinning doesn't count as an edit
+    fn set_pinned(&self, note_id: usize, pinned: bool) -> anyhow::Result<()>;
+    /// writes the notebooks and notes of an archive, all of them or none
+    fn restore_archive(
+        &self,
+        archive: &Archive,
+        conflict: Conflict,
+    ) -> anyhow::Result<ImportReport>;
+    fn add_notebook(&self, name: &str, parent_id: Option<usize>) -> anyhow::Result<usize>;
+    fn get_notebook(&self, notebook_id: usize) -> anyhow::Result<Option<Notebook>>;
+    /// every notebook, by name
+    fn get_notebooks(&self) -> anyhow::Result<Vec<Notebook>>;
+    /// commits the writes that wait for it, then pulls and pushes commits with the remote.
+    /// only stores kept in git can
+    fn sync(&self, _remote: &str) -> anyhow::Result<SyncReport> {
+        bail!("only git stores can sync")
+    }
+}
+
+/// the kinds of store a vault can be kept in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// a sqlite database
+    #[default]
+    Sqlite,
+    /// a directory of markdown files
+    Markdown,
+    /// a directory of markdown files committed to git
+    Git,
+}
+
+impl Display for StoreKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StoreKind::Sqlite => "sqlite",
+            StoreKind::Markdown => "markdown",
+            StoreKind::Git => "git",
+        })
+    }
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sqlite" => Ok(StoreKind::Sqlite),
+            "markdown" | "md" => Ok(StoreKind::Markdown),
+            "git" => Ok(StoreKind::Git),
+            _ => Err(format!("unknown store {s:?}, use sqlite, markdown or git")),
+        }
+    }
+}
+
+impl NoteStore for RepositoryPool {
+    fn get_notes(&self) -> anyhow::Result<Vec<Note>> {
+        Ok(self.get()?.get_notes()?)
+    }
+
+    fn get_note(&self, note_id: usize) -> anyhow::Result<Note> {
+        Ok(self.get()?.get_note(note_id)?)
+    }
+
+    fn add(&self, notes: &[Note]) -> anyhow::Result<Vec<Note>> {
+        Ok(self.get()?.add(notes)?)
+    }
+
+    fn upsert(&self, notes: &[Note]) -> anyhow::Result<Vec<Note>> {
+        Ok(self.get()?.upsert(notes)?)
+    }
+
+    fn insert(&self, note: &Note) -> anyhow::Result<usize> {
+        Ok(self.get()?.insert(note)?)
+    }
+
+    fn delete(&self, note_id: usize) -> anyhow::Result<Note> {
+        Ok(self.get()?.delete(note_id)?)
+    }
+
+    fn restore(&self, note_id: usize) -> anyhow::Result<()> {
+        Ok(self.get()?.restore(note_id)?)
+    }
+
+    fn get_trash(&self) -> anyhow::Result<Vec<Note>> {
+        Ok(self.get()?.get_trash()?)
+    }
+
+    fn purge(&self, note_id: usize) -> anyhow::Result<()> {
+        Ok(self.get()?.purge(note_id)?)
+    }
+
+    fn empty_trash(&self) -> anyhow::Result<usize> {
+        Ok(self.get()?.empty_trash()?)
+    }
+
+    fn purge_older_than(&self, days: u32) -> anyhow::Result<usize> {
+        Ok(self.get()?.purge_older_than(days)?)
+    }
+
+    fn update(&self, note_id: usize, note: Note) -> anyhow::Result<()> {
+        Ok(self.get()?.update(note_id, note)?)
+    }
+
+    fn update_all(&self, notes: &[Note]) -> anyhow::Result<()> {
+        Ok(self.get()?.update_all(notes)?)
+    }
+
+    fn get_links(&self, note_id: usize) -> anyhow::Result<Vec<NoteLink>> {
+        Ok(self.get()?.get_links(note_id)?)
+    }
+
+    fn get_backlinks(&self, note_id: usize) -> anyhow::Result<Vec<Note>> {
+        Ok(self.get()?.get_backlinks(note_id)?)
+    }
+
+    fn get_revisions(&self, note_id: usize) -> anyhow::Result<Vec<Revision>> {
+        Ok(self.get()?.get_revisions(note_id)?)
+    }
+
+    fn get_revision(&self, note_id: usize, revision: usize) -> anyhow::Result<Revision> {
+        Ok(self.get()?.get_revision(note_id, revision)?)
+    }
+
+    fn get_broken_links(&self) -> anyhow::Result<Vec<NoteLink>> {
+        Ok(self.get()?.get_broken_links()?)
+    }
+
+    fn move_note(&self, note_id: usize, notebook_id: Option<usize>) -> anyhow::Result<()> {
+        Ok(self.get()?.move_note(note_id, notebook_id)?)
+    }
+
+    fn set_pinned(&self, note_id: usize, pinned: bool) -> anyhow::Result<()> {
+        Ok(self.get()?.set_pinned(note_id, pinned)?)
+    }
+
+    fn restore_archive(
+        &self,
+        archive: &Archive,
+        conflict: Conflict,
+    ) -> anyhow::Result<ImportReport> {
+        Ok(self.get()?.restore_archive(archive, conflict)?)
+    }
+
+    fn add_notebook(&self, name: &str, parent_id: Option<usize>) -> anyhow::Result<usize> {
+        Ok(self.get()?.add_notebook(name, parent_id)?)
+    }
+
+    fn get_notebook(&self, notebook_id: usize) -> anyhow::Result<Option<Notebook>> {
+        Ok(self.get()?.get_notebook(notebook_id)?)
+    }
+
+    fn get_notebooks(&self) -> anyhow::Result<Vec<Notebook>> {
+        Ok(self.get()?.get_notebooks()?)
+    }
+}
@@ -0,0 +1,262 @@
+// colours of the tui. a theme is one of the built-in ones or a toml file, the file can
+// start from a built-in theme and change only some colours:
+//
+//     base = "dark"
+//     selected = "#ff8800"
+//     code_theme = "Solarized (dark)"
+//
+// colours are names like `yellow` or `light-blue`, `#rrggbb` or a palette index like `42`
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::config_dir, markdown};
+
+pub const DEFAULT_THEME: &str = "classic";
+
+/// names of the built-in themes, the first is the default
+pub const BUILTIN_THEMES: [&str; 5] = ["classic", "dark", "light", "high-contrast", "ansi16"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    pub background: Color,
+    pub text: Color,
+    pub border: Color,
+    /// notebooks, backlinks and other things that stand out from the text
+    pub accent: Color,
+    pub selected: Color,
+    /// border of the pane the keys go to
+    pub focused: Color,
+    /// title of the open tab
+    pub tab: Color,
+    /// key hints under a view
+    pub bar: Color,
+    pub bar_text: Color,
+    pub muted: Color,
+    /// broken links, removed lines and questions before deleting
+    pub warning: Color,
+    pub added: Color,
+    pub popup: Color,
+    pub heading: Color,
+    pub code: Color,
+    pub link: Color,
+    pub logo: Color,
+    pub logo_accent: Color,
+    pub normal_mode: Color,
+    pub insert_mode: Color,
+    /// syntax highlighting theme for fenced code, without one code is drawn in `code`.
+    /// highlighting uses true colour, terminals with 16 colours should set it to ""
+    pub code_theme: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::builtin(DEFAULT_THEME).expect("default theme is built in")
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let rgb = |hex: u32| Color::Rgb((hex >> 16) as u8, (hex >> 8) as u8, hex as u8);
+        let theme = match name {
+            "classic" => Theme {
+                background: Color::Cyan,
+                text: Color::Yellow,
+                border: Color::Yellow,
+                accent: Color::LightYellow,
+                selected: Color::Red,
+                focused: Color::Red,
+                tab: Color::Blue,
+                bar: Color::LightYellow,
+                bar_text: Color::Blue,
+                muted: Color::DarkGray,
+                warning: Color::Red,
+                added: Color::Green,
+                popup: Color::Black,
+                heading: Color::Cyan,
+                code: Color::Green,
+                link: Color::Blue,
+                logo: Color::Yellow,
+                logo_accent: Color::Blue,
+                normal_mode: Color::Yellow,
+                insert_mode: Color::LightGreen,
+                code_theme: Some("base16-ocean.dark".to_string()),
+            },
+            "dark" => Theme {
+                background: rgb(0x1e1e2e),
+                text: rgb(0xcdd6f4),
+                border: rgb(0x89b4fa),
+                accent: rgb(0xf9e2af),
+                selected: rgb(0xf38ba8),
+                focused: rgb(0xf38ba8),
+                tab: rgb(0x89b4fa),
+                bar: rgb(0x313244),
+                bar_text: rgb(0xcdd6f4),
+                muted: rgb(0x6c7086),
+                warning: rgb(0xf38ba8),
+                added: rgb(0xa6e3a1),
+                popup: rgb(0x181825),
+                heading: rgb(0x89dceb),
+                code: rgb(0xa6e3a1),
+                link: rgb(0x89b4fa),
+                logo: rgb(0xf9e2af),
+                logo_accent: rgb(0x89b4fa),
+                normal_mode: rgb(0x89b4fa),
+                insert_mode: rgb(0xa6e3a1),
+                code_theme: Some("base16-ocean.dark".to_string()),
+            },
+            "light" => Theme {
+                background: rgb(0xeff1f5),
+                text: rgb(0x4c4f69),
+                border: rgb(0x1e66f5),
+                accent: rgb(0xdf8e1d),
+                selected: rgb(0xd20f39),
+                focused: rgb(0xd20f39),
+                tab: rgb(0x1e66f5),
+                bar: rgb(0xccd0da),
+                bar_text: rgb(0x4c4f69),
+                muted: rgb(0x8c8fa1),
+                warning: rgb(0xd20f39),
+                added: rgb(0x40a02b),
+                popup: rgb(0xe6e9ef),
+                heading: rgb(0x1e66f5),
+                code: rgb(0x40a02b),
+                link: rgb(0x1e66f5),
+                logo: rgb(0xdf8e1d),
+                logo_accent: rgb(0x1e66f5),
+                normal_mode: rgb(0x1e66f5),
+                insert_mode: rgb(0x40a02b),
+                code_theme: Some("InspiredGitHub".to_string()),
+            },
+            "high-contrast" => Theme {
+                background: Color::Black,
+                text: Color::White,
+                border: Color::White,
+                accent: Color::LightYellow,
+                selected: Color::LightCyan,
+                focused: Color::LightYellow,
+                tab: Color::LightYellow,
+                bar: Color::White,
+                bar_text: Color::Black,
+                muted: Color::Gray,
+                warning: Color::LightRed,
+                added: Color::LightGreen,
+                popup: Color::Black,
+                heading: Color::LightCyan,
+                code: Color::LightGreen,
+                link: Color::LightCyan,
+                logo: Color::White,
+                logo_accent: Color::LightYellow,
+                normal_mode: Color::White,
+                insert_mode: Color::LightGreen,
+                code_theme: Some("base16-eighties.dark".to_string()),
+            },
+            // only the 16 colours every terminal has, the terminal decides how they look
+            "ansi16" => Theme {
+                background: Color::Reset,
+                text: Color::Reset,
+                border: Color::Blue,
+                accent: Color::Yellow,
+                selected: Color::Red,
+                focused: Color::Magenta,
+                tab: Color::Blue,
+                bar: Color::Blue,
+                bar_text: Color::White,
+                muted: Color::DarkGray,
+                warning: Color::Red,
+                added: Color::Green,
+                popup: Color::Black,
+                heading: Color::Cyan,
+                code: Color::Green,
+                link: Color::Blue,
+                logo: Color::Yellow,
+                logo_accent: Color::Blue,
+                normal_mode: Color::Blue,
+                insert_mode: Color::Green,
+                code_theme: None,
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// a built-in theme, a toml file or a theme in the themes directory of the config
+    pub fn load(name: &str) -> anyhow::Result<Theme> {
+        if let Some(theme) = Theme::builtin(name) {
+            return Ok(theme);
+        }
+        let path = if name.ends_with(".toml") || name.contains('/') {
+            PathBuf::from(name)
+        } else {
+            config_dir().join("themes").join(format!("{name}.toml"))
+        };
+        let text = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "theme {name} is neither one of {} nor a file at {}",
+                BUILTIN_THEMES.join(", "),
+                path.display()
+            )
+        })?;
+        Theme::parse(&text).with_context(|| format!("theme file {}", path.display()))
+    }
+
+    /// a theme file over its `base`, the default theme when it has none
+    pub fn parse(text: &str) -> anyhow::Result<Theme> {
+        let mut table: toml::Table = toml::from_str(text)?;
+        let base = match table.remove("base") {
+            Some(toml::Value::String(base)) => match Theme::builtin(&base) {
+                Some(theme) => theme,
+                None => bail!("unknown base theme {base:?}"),
+            },
+            Some(_) => bail!("base must be the name of a built-in theme"),
+            None => Theme::default(),
+        };
+        let toml::Value::Table(mut theme) = toml::Value::try_from(base)? else {
+            unreachable!("a theme is a table");
+        };
+        theme.extend(table);
+        let mut theme: Theme = toml::Value::Table(theme).try_into()?;
+        match theme.code_theme.as_deref() {
+            Some("") => theme.code_theme = None,
+            Some(name) if !markdown::is_code_theme(name) => bail!(
+                "unknown code_theme {name:?}, one of {}",
+                markdown::code_theme_names().join(", ")
+            ),
+            _ => (),
+        }
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Theme, BUILTIN_THEMES};
+    use ratatui::style::Color;
+
+    #[test]
+    fn themes() {
+        for name in BUILTIN_THEMES {
+            assert!(Theme::builtin(name).is_some(), "{name}");
+        }
+        let theme =
+            Theme::parse("base = \"ansi16\"\nselected = \"#ff8800\"\nborder = \"light-blue\"")
+                .unwrap();
+        assert_eq!(theme.selected, Color::Rgb(0xff, 0x88, 0x00));
+        assert_eq!(theme.border, Color::LightBlue);
+        assert_eq!(theme.text, Theme::builtin("ansi16").unwrap().text);
+        assert_eq!(theme.code_theme, None);
+        let theme = Theme::parse("code_theme = \"\"").unwrap();
+        assert_eq!(theme.code_theme, None);
+        assert!(Theme::parse("code_theme = \"Solarized (light)\"").is_ok());
+        assert!(Theme::parse("code_theme = \"sepia\"").is_err());
+
+        assert_eq!(Theme::parse("").unwrap(), Theme::default());
+        assert!(Theme::parse("base = \"sepia\"").is_err());
+        assert!(Theme::parse("borders = \"red\"").is_err());
+        assert!(Theme::parse("border = \"not a colour\"").is_err());
+    }
+}
@@ -1,16 +1,28 @@
 use std::borrow::BorrowMut;
 
 use ratatui::{
-    layout::{Alignment, Constraint, Layout, Rect},
+    layout::{Alignment, Constraint, Layout, Margin, Rect},
     style::{Style, Stylize},
-    text::{self, Text},
-    widgets::{Block, BorderType, Borders, List, Padding, Paragraph, Tabs, Wrap},
+    text::{self, Line, Text},
+    widgets::{
+        Block, BorderType, Borders, Clear, List, ListItem, Padding, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Tabs, Wrap,
+    },
     Frame,
 };
 
 #[allow(clippy::wildcard_imports)]
 use crate::app::App;
-use crate::{app::AppMode, widgets};
+use crate::{
+    app::{AppMode, Confirm, Focus, InputMode, NoteList, TreeRow, Unlock},
+    config::DEFAULT_PROFILE,
+    diff::{diff_lines, DiffLine},
+    keymap::KeyScope,
+    markdown,
+    repository::Revision,
+    theme::Theme,
+    widgets,
+};
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::vertical([
@@ -20,16 +32,136 @@ pub fn draw(f: &mut Frame, app: &mut App) {
     ])
     .split(f.size());
     app.clibrate_logo_position(chunks[1].width); // calibrate the position to not go out of area width
-    f.render_widget(Block::new().on_cyan(), f.size());
+    f.render_widget(Block::new().bg(app.theme.background), f.size());
     render_tabs(f, app, chunks[0]);
     render_app(f, app, chunks[1]);
-    draw_footer(f, chunks[2]);
+    draw_footer(f, app, chunks[2]);
+    if app.vaults.is_some() {
+        render_vaults(f, app, chunks[1]);
+    }
+    if let Some(unlock) = &app.unlock {
+        render_unlock(f, unlock, &app.theme, chunks[1]);
+    }
+    if app.show_help {
+        render_help(f, app, chunks[1]);
+    }
+}
+
+/// area of the given size in the middle of `area`
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// profiles of the config, the database of the selected one opens on enter
+fn render_vaults(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = &app.theme;
+    let Some(vaults) = app.vaults.as_mut() else {
+        return;
+    };
+    let open = app.config.profile.as_deref();
+    let items: Vec<ListItem> = vaults
+        .names
+        .iter()
+        .map(|name| {
+            let marker = if Some(name.as_str()) == open {
+                "● "
+            } else {
+                "  "
+            };
+            let db = app
+                .config
+                .profiles
+                .get(name)
+                .map(|profile| profile.db.display().to_string())
+                .unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                format!("{marker}{name}  ").fg(theme.text),
+                db.fg(theme.muted),
+            ]))
+        })
+        .collect();
+    let popup = centered(area, 60, items.len() as u16 + 2);
+    let list = List::new(items)
+        .highlight_symbol("=>")
+        .highlight_style(Style::default().fg(theme.selected))
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Double)
+                .fg(theme.border)
+                .title(format!("vaults · {}", app.keymap.hints(KeyScope::Vaults))),
+        )
+        .bg(theme.popup);
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(list, popup, &mut vaults.state);
+}
+
+/// the passphrase of an encrypted vault, one dot per character
+pub fn render_unlock(f: &mut Frame, unlock: &Unlock, theme: &Theme, area: Rect) {
+    let name = unlock.profile.as_deref().unwrap_or("vault");
+    let mut lines = vec![Line::from(vec![
+        "passphrase: ".fg(theme.muted),
+        "•".repeat(unlock.passphrase.chars().count()).fg(theme.text),
+        "█".fg(theme.accent),
+    ])];
+    if let Some(error) = &unlock.error {
+        lines.push(Line::from(error.as_str().fg(theme.warning)));
+    }
+    let popup = centered(area, 50, lines.len() as u16 + 2);
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Double)
+                .fg(theme.border)
+                .title(format!("unlock {name} · enter open · esc cancel")),
+        )
+        .bg(theme.popup);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+/// every binding of the current view and the global ones, over the view
+fn render_help(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let scope = app.key_scope();
+    let mut scopes = vec![scope];
+    if scope != KeyScope::Global {
+        scopes.push(KeyScope::Global);
+    }
+    let lines: Vec<Line> = scopes
+        .into_iter()
+        .flat_map(|scope| app.keymap.bindings(scope))
+        .map(|binding| {
+            let keys: Vec<String> = binding.keys.iter().map(ToString::to_string).collect();
+            Line::from(vec![
+                format!("{:<16}", keys.join(", ")).fg(theme.accent).bold(),
+                binding.action.description().into(),
+            ])
+        })
+        .collect();
+    let popup = centered(area, 50, lines.len() as u16 + 2);
+    let help = Paragraph::new(lines).block(
+        Block::bordered()
+            .border_type(BorderType::Double)
+            .fg(theme.border)
+            .title(format!("{} keys · any key closes", scope.name())),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(help.bg(theme.popup), popup);
 }
 pub fn render_app(f: &mut Frame, app: &mut App, area: Rect) {
     match app.mode {
         AppMode::Home => {
             f.render_widget(
-                widgets::NotesLogo::default().set_position(app.logo_position),
+                widgets::NotesLogo::default()
+                    .set_position(app.logo_position)
+                    .set_colors(app.theme.logo, app.theme.logo_accent),
                 area,
             );
         }
@@ -37,89 +169,318 @@ pub fn render_app(f: &mut Frame, app: &mut App, area: Rect) {
             render_note_view(f, app, area);
         }
         AppMode::Search => render_search(f, app, area),
+        AppMode::Trash => render_trash(f, app, area),
         _ => (),
     }
 }
 pub fn render_search(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = &app.theme;
     // divide the layout
     let vertical =
         Layout::vertical([Constraint::Percentage(100), Constraint::Length(2)]).split(area); // small area to add view note keys(add, edit, delete)
-    let helpers = Paragraph::new(app.search_query.as_str())
-        .on_light_yellow()
-        .blue();
-    f.render_widget(helpers, vertical[1]);
+    let helpers = match app.input_mode {
+        InputMode::Insert => Line::raw(app.search_query.as_str()),
+        InputMode::Normal => Line::from(vec![
+            app.search_query.as_str().into(),
+            format!("  {}", app.keymap.hints(KeyScope::Search)).fg(theme.muted),
+        ]),
+    };
+    f.render_widget(
+        Paragraph::new(helpers).bg(theme.bar).fg(theme.bar_text),
+        vertical[1],
+    );
+    if app.input_mode == InputMode::Insert {
+        let before: String = app.search_query.chars().take(app.search_cursor).collect();
+        let x = vertical[1].x + Line::raw(before).width() as u16;
+        f.set_cursor(x.min(vertical[1].right().saturating_sub(1)), vertical[1].y);
+    }
     let chunks = Layout::horizontal([Constraint::Percentage(20), Constraint::Percentage(80)])
         .split(vertical[0]);
     let titles = List::new(
         app.get_search_result()
             .iter()
-            .map(|note| Text::raw(note.title.as_str()).yellow()),
+            .map(|note| Text::raw(note.title.as_str()).fg(theme.text)),
     )
     .block(
         Block::bordered()
             .border_type(BorderType::Double)
-            .yellow()
+            .fg(theme.border)
             .title("title"),
     );
     f.render_widget(titles, chunks[0]);
     let selected_note = app.note_list.get_selected();
     let content = if let Some(note) = selected_note {
-        note.content.as_str()
+        note.text.as_str()
     } else {
         "Search Something!"
     };
-    let content = Paragraph::new(content).wrap(Wrap { trim: true }).block(
-        Block::bordered()
-            .border_type(BorderType::Double)
-            .yellow()
-            .title("content"),
-    );
+    let content = Paragraph::new(content)
+        .wrap(Wrap { trim: true })
+        .fg(theme.text)
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Double)
+                .fg(theme.border)
+                .title("content"),
+        );
     f.render_widget(content, chunks[1]);
 }
 
 pub fn render_note_view(f: &mut Frame, app: &mut App, area: Rect) {
+    if app.history.is_some() {
+        return render_history(f, app, area);
+    }
     // divide the layout
     let vertical =
-        Layout::vertical([Constraint::Percentage(100), Constraint::Length(1)]).split(area); // small area to add view note keys(add, edit, delete)
-    let helpers = Paragraph::new("Press a: add, e: edit, d: delete")
-        .on_light_yellow()
-        .blue();
+        Layout::vertical([Constraint::Percentage(100), Constraint::Length(2)]).split(area); // small area to add view note keys(add, edit, delete)
+    let helpers = helpers(app, app.keymap.hints(KeyScope::Notes));
     f.render_widget(helpers, vertical[1]);
     let chunks = Layout::horizontal([Constraint::Percentage(20), Constraint::Percentage(80)])
         .split(vertical[0]);
     let titles = List::new(
         app.note_list
-            .notes
+            .rows
             .iter()
-            .map(|note| Text::raw(note.title.as_str()).yellow()),
+            .map(|&row| tree_item(&app.note_list, row, &app.theme)),
     )
     .highlight_symbol("=>")
-    .highlight_style(Style::default().red())
+    .highlight_style(Style::default().fg(app.theme.selected))
     .block(
         Block::bordered()
             .border_type(BorderType::Double)
-            .yellow()
-            .title("title"),
+            .fg(app.theme.border)
+            .title(format!("title · by {}", app.note_list.sort)),
     );
     f.render_stateful_widget(titles, chunks[0], app.note_list.state.borrow_mut());
     let selected_note = app.note_list.get_selected();
-    let content = if let Some(note) = selected_note {
-        note.content.as_str()
+    let content = match selected_note {
+        Some(note) if app.raw_content => Text::raw(note.text.clone()),
+        Some(note) => markdown::render(&note.text, &app.theme),
+        None => Text::raw("Select Note to Show!"),
+    };
+    let title = if app.raw_content {
+        "content · raw"
     } else {
-        "Select Note to Show!"
+        "content"
     };
-    let content = Paragraph::new(content).wrap(Wrap { trim: true }).block(
+    let focused = app.note_list.focus == Focus::Content;
+    let block = Block::bordered()
+        .border_type(BorderType::Double)
+        .title(title)
+        .fg(if focused {
+            app.theme.focused
+        } else {
+            app.theme.border
+        });
+    let right =
+        Layout::vertical([Constraint::Percentage(75), Constraint::Percentage(25)]).split(chunks[1]);
+    let inner = block.inner(right[0]);
+    let content = Paragraph::new(content)
+        .wrap(Wrap { trim: false })
+        .fg(app.theme.text);
+    // keep the last line at the bottom of the pane instead of scrolling past it
+    let max_scroll = (content.line_count(inner.width) as u16).saturating_sub(inner.height);
+    let scroll = app.note_list.content_scroll().min(max_scroll);
+    if scroll != app.note_list.content_scroll() {
+        app.note_list.scroll_to(scroll);
+    }
+    app.note_list.content_height = inner.height;
+    f.render_widget(content.scroll((scroll, 0)).block(block), right[0]);
+    if max_scroll > 0 {
+        let mut state = ScrollbarState::new(max_scroll as usize).position(scroll as usize);
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight),
+            right[0].inner(&Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut state,
+        );
+    }
+    render_links(f, app, right[1]);
+}
+
+/// deleted notes, they can be restored or deleted for good
+pub fn render_trash(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = &app.theme;
+    let vertical =
+        Layout::vertical([Constraint::Percentage(100), Constraint::Length(1)]).split(area);
+    let helpers = helpers(app, app.keymap.hints(KeyScope::Trash));
+    f.render_widget(helpers, vertical[1]);
+    let chunks = Layout::horizontal([Constraint::Percentage(20), Constraint::Percentage(80)])
+        .split(vertical[0]);
+    let titles = List::new(
+        app.trash
+            .notes
+            .iter()
+            .map(|note| Text::raw(note.title.as_str()).fg(theme.text)),
+    )
+    .highlight_symbol("=>")
+    .highlight_style(Style::default().fg(theme.selected))
+    .block(
         Block::bordered()
             .border_type(BorderType::Double)
-            .yellow()
-            .title("content"),
+            .fg(theme.border)
+            .title("trash"),
     );
+    f.render_stateful_widget(titles, chunks[0], &mut app.trash.state);
+    let (title, content) = match app.trash.get_selected() {
+        Some(note) => (
+            format!(
+                "deleted at {}",
+                note.deleted_at.as_deref().unwrap_or_default()
+            ),
+            note.text.as_str(),
+        ),
+        None => ("content".to_string(), "Trash is empty!"),
+    };
+    let content = Paragraph::new(content)
+        .wrap(Wrap { trim: true })
+        .fg(theme.text)
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Double)
+                .fg(theme.border)
+                .title(title),
+        );
     f.render_widget(content, chunks[1]);
 }
 
+/// key helpers of a view, or the question when an action waits for confirmation
+fn helpers(app: &App, keys: String) -> Paragraph<'static> {
+    let theme = &app.theme;
+    let question = match &app.confirm {
+        Some(Confirm::Delete { title, .. }) => format!("Move \"{title}\" to the trash?"),
+        Some(Confirm::Purge { title, .. }) => format!("Delete \"{title}\" forever?"),
+        None => {
+            return Paragraph::new(keys)
+                .wrap(Wrap { trim: true })
+                .bg(theme.bar)
+                .fg(theme.bar_text)
+        }
+    };
+    let yes = app
+        .keymap
+        .bindings(KeyScope::Confirm)
+        .find_map(|binding| binding.keys.first())
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    Paragraph::new(format!("{question} {yes}: yes, any other key: no"))
+        .bg(theme.bar)
+        .fg(theme.warning)
+        .bold()
+}
+
+/// revisions of a note next to what the selected one changed
+fn render_history(f: &mut Frame, app: &mut App, area: Rect) {
+    let Some(history) = app.history.as_mut() else {
+        return;
+    };
+    let vertical =
+        Layout::vertical([Constraint::Percentage(100), Constraint::Length(1)]).split(area);
+    let theme = &app.theme;
+    let helpers = Paragraph::new(app.keymap.hints(KeyScope::History))
+        .bg(theme.bar)
+        .fg(theme.bar_text);
+    f.render_widget(helpers, vertical[1]);
+    let chunks = Layout::horizontal([Constraint::Percentage(20), Constraint::Percentage(80)])
+        .split(vertical[0]);
+    let revisions = List::new(history.revisions.iter().map(|revision| {
+        ListItem::new(format!("#{} {}", revision.revision, revision.created_at)).fg(theme.text)
+    }))
+    .highlight_symbol("=>")
+    .highlight_style(Style::default().fg(theme.selected))
+    .block(
+        Block::bordered()
+            .border_type(BorderType::Double)
+            .fg(theme.border)
+            .title("history"),
+    );
+    f.render_stateful_widget(revisions, chunks[0], &mut history.state);
+
+    let new = history
+        .selected()
+        .map(Revision::content)
+        .unwrap_or_default();
+    let old = history
+        .before_selected()
+        .map(Revision::content)
+        .unwrap_or_default();
+    let lines: Vec<Line> = diff_lines(&old, &new)
+        .into_iter()
+        .map(|line| match line {
+            DiffLine::Same(line) => Line::raw(format!("  {line}")).fg(theme.text),
+            DiffLine::Added(line) => Line::raw(format!("+ {line}")).fg(theme.added),
+            DiffLine::Removed(line) => Line::raw(format!("- {line}")).fg(theme.warning),
+        })
+        .collect();
+    let diff = Paragraph::new(lines).block(
+        Block::bordered()
+            .border_type(BorderType::Double)
+            .fg(theme.border)
+            .title("changes"),
+    );
+    f.render_widget(diff, chunks[1]);
+}
+
+/// links of the selected note and the notes linking back to it
+fn render_links(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = &app.theme;
+    let note_list = &mut app.note_list;
+    let links = note_list.links.iter().map(|link| match link.target_id {
+        Some(_) => ListItem::new(format!("→ {}", link.target)).fg(theme.text),
+        None => ListItem::new(format!("✗ {} (broken)", link.target)).fg(theme.warning),
+    });
+    let backlinks = note_list
+        .backlinks
+        .iter()
+        .map(|note| ListItem::new(format!("← {}", note.title)).fg(theme.accent));
+    let list = List::new(links.chain(backlinks))
+        .highlight_symbol("=>")
+        .highlight_style(Style::default().fg(theme.selected))
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Double)
+                .fg(theme.border)
+                .title("links"),
+        );
+    f.render_stateful_widget(list, area, &mut note_list.link_state);
+}
+
+fn tree_item(note_list: &NoteList, row: TreeRow, theme: &Theme) -> ListItem<'static> {
+    match row {
+        TreeRow::Notebook { id, depth } => {
+            let marker = if note_list.collapsed.contains(&id) {
+                "▸"
+            } else {
+                "▾"
+            };
+            let name = note_list
+                .notebook(id)
+                .map(|notebook| notebook.name.as_str())
+                .unwrap_or_default();
+            ListItem::new(format!("{}{marker} {name}", "  ".repeat(depth)))
+                .fg(theme.accent)
+                .bold()
+        }
+        TreeRow::Note { index, depth } => {
+            let note = &note_list.notes[index];
+            let cut = if note.id.is_some() && note.id == note_list.cut {
+                "✂ "
+            } else {
+                ""
+            };
+            let pin = if note.pinned { "📌 " } else { "" };
+            ListItem::new(format!("{}  {cut}{pin}{}", "  ".repeat(depth), note.title))
+                .fg(theme.text)
+        }
+    }
+}
+
 pub fn render_tabs(f: &mut Frame, app: &mut App, area: Rect) {
-    // Calculate padding to center tabs
-    let remaining_width = area.width as isize - 30;
+    // Calculate padding to center tabs, each title has a divider and a space on both sides
+    let tabs_width: usize = app.tabs.items.iter().map(|title| title.len() + 3).sum();
+    let remaining_width = area.width as isize - tabs_width as isize;
     let padding = (remaining_width / 2).max(0) as u16;
     let tabs = app
         .tabs
@@ -131,24 +492,46 @@ pub fn render_tabs(f: &mut Frame, app: &mut App, area: Rect) {
             Block::new()
                 .borders(Borders::BOTTOM)
                 .border_type(BorderType::QuadrantInside)
-                .title("Note Menu".yellow())
+                .title(
+                    format!(
+                        "Note Menu · {}",
+                        app.config.profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+                    )
+                    .fg(app.theme.text),
+                )
                 .title_alignment(Alignment::Center)
                 .title_style(Style::default().bold())
-                .light_yellow()
+                .fg(app.theme.accent)
                 .padding(Padding::new(padding, padding, 1, 0)),
         )
-        .highlight_style(Style::default().blue())
+        .highlight_style(Style::default().fg(app.theme.tab))
         .select(app.tabs.state.selected().unwrap_or(0));
     f.render_widget(tabs, area)
 }
-pub fn draw_footer(f: &mut Frame, area: Rect) {
-    let footer = Paragraph::new("Take a Great Care of Yourself😊")
-        .style(Style::default().yellow().bold())
+pub fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
+    let footer = match &app.status {
+        Some(status) => Paragraph::new(status.as_str()).fg(app.theme.warning),
+        None => Paragraph::new("Take a Great Care of Yourself😊").fg(app.theme.text),
+    };
+    let footer = footer
+        .bold()
         .block(
             Block::new()
-                .style(Style::new().black())
+                .style(Style::new().fg(app.theme.popup))
                 .padding(Padding::top(1)),
         )
         .centered();
-    f.render_widget(footer, area)
+    f.render_widget(footer, area);
+    // which mode the keys go to, on the same line as the message
+    let mode = match app.input_mode {
+        InputMode::Normal => " NORMAL ".fg(app.theme.popup).bg(app.theme.normal_mode),
+        InputMode::Insert => " INSERT ".fg(app.theme.popup).bg(app.theme.insert_mode),
+    };
+    let mode_area = Rect {
+        y: area.y + 1,
+        height: area.height.saturating_sub(1).min(1),
+        width: (mode.width() as u16).min(area.width),
+        ..area
+    };
+    f.render_widget(Paragraph::new(Line::from(mode).bold()), mode_area);
 }
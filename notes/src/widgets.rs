@@ -1,11 +1,8 @@
-use ratatui::{
-    layout::{Margin, Position},
-    style::Color,
-    widgets::Widget,
-};
+use ratatui::{layout::Margin, style::Color, widgets::Widget};
 
 /// contains custom widgets
 
+#[allow(clippy::empty_line_after_doc_comments)]
 const NOTE_LOGO: &str = "                                                            
       ░████████████████████████████████████████████████░    
       ░█████████████████████████████████████████████████    
@@ -119,14 +116,20 @@ pub struct NotesLogo {
     pen_logo: Vec<&'static str>,
     note_string_logo: Vec<&'static str>,
     position: u16,
+    color: Color,
+    accent: Color,
 }
 impl NotesLogo {
     pub fn set_position(self, position: u16) -> Self {
+        NotesLogo { position, ..self }
+    }
+
+    /// colour of the note and of the pen with the text
+    pub fn set_colors(self, color: Color, accent: Color) -> Self {
         NotesLogo {
-            position,
-            note_logo: self.note_logo,
-            pen_logo: self.pen_logo,
-            note_string_logo: self.note_string_logo,
+            color,
+            accent,
+            ..self
         }
     }
 }
@@ -141,6 +144,8 @@ impl Default for NotesLogo {
             pen_logo,
             note_string_logo,
             position: 0,
+            color: Color::Yellow,
+            accent: Color::Blue,
         }
     }
 }
@@ -165,7 +170,7 @@ impl Widget for NotesLogo {
             for (x, char) in line1.chars().enumerate() {
                 let x = (self.position + center_area.left() + x as u16) % area.width;
                 let y = center_area.top() + y as u16;
-                buf.get_mut(x, y).set_char(char).set_fg(Color::Yellow);
+                buf.get_mut(x, y).set_char(char).set_fg(self.color);
                 right_padding += 1;
             }
             let mut new_right_padding = 0;
@@ -173,7 +178,7 @@ impl Widget for NotesLogo {
                 let x =
                     (right_padding + self.position + center_area.left() + x as u16) % area.width;
                 let y = center_area.top() + y as u16;
-                buf.get_mut(x, y).set_char(char).set_fg(Color::Blue);
+                buf.get_mut(x, y).set_char(char).set_fg(self.accent);
                 new_right_padding += 1;
             }
             right_padding += new_right_padding;
@@ -181,7 +186,7 @@ impl Widget for NotesLogo {
                 let x =
                     (right_padding + self.position + center_area.left() + x as u16) % area.width;
                 let y = center_area.top() + y as u16;
-                buf.get_mut(x, y).set_char(char).set_fg(Color::Blue);
+                buf.get_mut(x, y).set_char(char).set_fg(self.accent);
             }
         }
     }